@@ -0,0 +1,318 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, Node, NodeIndex},
+    scenarios::Scenario,
+    settings::Settings,
+    sourcefile::SourceKind,
+};
+use path_clean::PathClean;
+use petgraph::{visit::EdgeRef, Direction};
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// The files impacted by a set of modified files
+struct Affected<'a> {
+    sources: Vec<&'a PathBuf>,
+    mains: Vec<&'a PathBuf>,
+    projects: Vec<&'a PathBuf>,
+
+    // The modified files that are not part of any project
+    ignored: Vec<&'a PathBuf>,
+}
+
+/// Report the source files, main units and projects impacted by a set of
+/// modified files.
+pub struct ActionAffected {
+    pub paths: Vec<PathBuf>,
+
+    // Also add the files modified since this git revision
+    pub since: Option<String>,
+
+    // Also read the list of modified files from stdin, one per line
+    pub stdin: bool,
+}
+
+impl ActionAffected {
+    /// Normalize a path read from stdin or git, relative to `dir`, so that
+    /// it matches the paths of the source files in the environment.
+    fn normalize(dir: &Path, path: &str, settings: &Settings) -> PathBuf {
+        Environment::source_path(dir.join(path).clean(), settings)
+    }
+
+    /// The files modified since a given revision, including the changes in
+    /// the working tree.
+    fn git_changes(
+        rev: &str,
+        settings: &Settings,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let git = |args: &[&str]| -> Result<String, Error> {
+            let out = std::process::Command::new("git").args(args).output()?;
+            if !out.status.success() {
+                return Err(Error::GitFailed(
+                    String::from_utf8_lossy(&out.stderr).trim().to_string(),
+                ));
+            }
+            Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+        };
+        let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+        let toplevel = Path::new(toplevel.trim());
+        Ok(git(&["diff", "--name-only", rev, "--"])?
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| ActionAffected::normalize(toplevel, l, settings))
+            .collect())
+    }
+
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        let mut changed: Vec<PathBuf> = self.paths.clone();
+        if let Some(rev) = &self.since {
+            changed.extend(ActionAffected::git_changes(rev, settings)?);
+        }
+        if self.stdin {
+            let cwd = std::env::current_dir()?;
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                let line = line.trim();
+                if !line.is_empty() {
+                    changed
+                        .push(ActionAffected::normalize(&cwd, line, settings));
+                }
+            }
+        }
+
+        let for_scenario = settings.cli_scenario(&env.scenarios)?;
+        if for_scenario != Scenario::default() {
+            println!(
+                "Limit result to {}",
+                env.scenarios.describe(&for_scenario)
+            );
+        }
+
+        let affected =
+            ActionAffected::find_affected(env, &changed, &for_scenario);
+        settings.print_files("Affected source files", affected.sources, false);
+        settings.print_files("Affected main units", affected.mains, false);
+        settings.print_files("Affected projects", affected.projects, false);
+        settings.print_files(
+            "Changed files not in any project",
+            affected.ignored,
+            true,
+        );
+        Ok(())
+    }
+
+    /// Walk the dependency graph backward from the changed files
+    fn find_affected<'a>(
+        env: &'a Environment,
+        changed: &'a [PathBuf],
+        for_scenario: &Scenario,
+    ) -> Affected<'a> {
+        let matches = |s: &Scenario| -> bool {
+            !env.scenarios.never_matches(&(s & for_scenario))
+        };
+
+        // Changing a project impacts the compilation of all its sources
+        let mut seeds: Vec<NodeIndex> = Vec::new();
+        let mut changed_gprs: HashSet<NodeIndex> = HashSet::new();
+        let mut ignored: Vec<&PathBuf> = Vec::new();
+        for path in changed {
+            if let Some(file) = env.files.get(path) {
+                seeds.push(file.borrow().file_node);
+            } else if let Some(gpr) = env.gprs.get(path) {
+                changed_gprs.insert(gpr.node);
+                seeds.extend(
+                    env.graph
+                        .0
+                        .edges_directed(gpr.node, Direction::Outgoing)
+                        .filter(|e| match e.weight() {
+                            Edge::ProjectSource(s) => matches(s),
+                            _ => false,
+                        })
+                        .map(|e| e.target()),
+                );
+            } else {
+                ignored.push(path);
+            }
+        }
+
+        // Walk back to the files that import a changed spec.  The bodies
+        // and separates of a unit are compiled against its spec, so they are
+        // impacted too.  Even a limited with reads the spec of the unit, so
+        // must be compiled again when it changes.
+        let mut affected: HashSet<NodeIndex> = HashSet::new();
+        let mut visited: HashSet<NodeIndex> = HashSet::new();
+        while let Some(node) = seeds.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            match env.graph.0[node] {
+                Node::Source(_) => {
+                    affected.insert(node);
+                    seeds.extend(
+                        env.graph
+                            .0
+                            .edges_directed(node, Direction::Incoming)
+                            .filter(|e| match e.weight() {
+                                Edge::UnitSource((SourceKind::Spec, s)) => {
+                                    matches(s)
+                                }
+                                // An include imports the header file itself
+                                Edge::SourceImports(_) => true,
+                                _ => false,
+                            })
+                            .map(|e| e.source()),
+                    );
+                }
+                Node::Unit(_) => {
+                    for e in
+                        env.graph.0.edges_directed(node, Direction::Incoming)
+                    {
+                        if let Edge::SourceImports(_) = e.weight() {
+                            seeds.push(e.source());
+                        }
+                    }
+                    for e in
+                        env.graph.0.edges_directed(node, Direction::Outgoing)
+                    {
+                        if let Edge::UnitSource((_, s)) = e.weight() {
+                            if matches(s) {
+                                seeds.push(e.target());
+                            }
+                        }
+                    }
+                }
+                Node::Project(_) => {}
+            }
+        }
+
+        let mut projects: HashSet<NodeIndex> = changed_gprs;
+        for node in &affected {
+            projects.extend(
+                env.graph
+                    .0
+                    .edges_directed(*node, Direction::Incoming)
+                    .filter(|e| match e.weight() {
+                        Edge::ProjectSource(s) => matches(s),
+                        _ => false,
+                    })
+                    .map(|e| e.source()),
+            );
+        }
+
+        let sources: Vec<&PathBuf> = affected
+            .iter()
+            .filter_map(|n| env.graph.get_source(*n).ok())
+            .collect();
+        let mains: Vec<&PathBuf> = sources
+            .iter()
+            .filter(|p| env.files[**p].borrow().is_ever_main)
+            .copied()
+            .collect();
+        let projects: Vec<&PathBuf> = projects
+            .iter()
+            .filter_map(|n| env.graph.get_project(*n).ok())
+            .collect();
+
+        Affected {
+            sources,
+            mains,
+            projects,
+            ignored,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_affected::ActionAffected;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+    use crate::scenarios::Scenario;
+    use crate::settings::Settings;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn normalize() {
+        let settings = Settings::default();
+        let dir = Path::new("/repo/sub");
+        assert_eq!(
+            ActionAffected::normalize(dir, "./src/a.adb", &settings),
+            PathBuf::from("/repo/sub/src/a.adb"),
+        );
+        assert_eq!(
+            ActionAffected::normalize(dir, "../x/src/a.adb", &settings),
+            PathBuf::from("/repo/x/src/a.adb"),
+        );
+        assert_eq!(
+            ActionAffected::normalize(dir, "/abs/a.adb", &settings),
+            PathBuf::from("/abs/a.adb"),
+        );
+    }
+
+    #[test]
+    fn changed_spec() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "affected",
+            &[
+                ("p.gpr", "project P is for Main use (\"main.adb\"); end P;"),
+                ("a.ads", "package A is end A;"),
+                ("a.adb", "package body A is end A;"),
+                ("b.ads", "with A; package B is end B;"),
+                ("b.adb", "package body B is end B;"),
+                ("main.adb", "with B; procedure Main is begin null; end;"),
+                ("other.ads", "package Other is end Other;"),
+            ],
+        )?;
+        let changed =
+            vec![settings.relto.join("a.ads"), settings.relto.join("x.adb")];
+        let affected =
+            ActionAffected::find_affected(&env, &changed, &Scenario::default());
+        let display = |paths: Vec<&PathBuf>| {
+            let mut paths: Vec<String> = paths
+                .into_iter()
+                .map(|p| settings.display_path(p).to_string())
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(
+            display(affected.sources),
+            vec!["a.adb", "a.ads", "b.adb", "b.ads", "main.adb"],
+        );
+        assert_eq!(display(affected.mains), vec!["main.adb"]);
+        assert_eq!(display(affected.projects), vec!["p.gpr"]);
+        assert_eq!(display(affected.ignored), vec!["x.adb"]);
+        Ok(())
+    }
+
+    #[test]
+    fn changed_header() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "affected_header",
+            &[
+                ("p.gpr", "project P is for Languages use (\"C\"); end P;"),
+                ("a.c", "#include \"b.h\"\nint main() {}\n"),
+                ("b.h", "int b();\n"),
+                ("b.c", "#include \"b.h\"\nint b() {}\n"),
+                ("c.c", "int c() {}\n"),
+            ],
+        )?;
+        let changed = vec![settings.relto.join("b.h")];
+        let affected =
+            ActionAffected::find_affected(&env, &changed, &Scenario::default());
+        let mut sources: Vec<String> = affected
+            .sources
+            .into_iter()
+            .map(|p| settings.display_path(p).to_string())
+            .collect();
+        sources.sort();
+        assert_eq!(sources, vec!["a.c", "b.c", "b.h"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, NodeIndex},
+    scenarios::Scenario,
+    settings::Settings,
+    sourcefile::{ImportKind, SourceKind},
+};
+use petgraph::{
+    algo::tarjan_scc, graph::Graph, visit::EdgeRef, Directed, Direction,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// A graph of the source files to compile.  Each node's weight is the node in
+// the full dependency graph, and there is an edge from each spec to the files
+// that need it to compile.
+type CompileGraph = Graph<NodeIndex, (), Directed, u32>;
+type CompileIndex = petgraph::graph::NodeIndex<u32>;
+
+// Groups of files, for instance the files compiled in each wave
+type FileGroups<'a> = Vec<Vec<&'a PathBuf>>;
+
+/// Compute a compilation order for the sources of a project.
+/// Files are grouped in waves: all files in a wave can be compiled in
+/// parallel, since they only need specs compiled in earlier waves.  Separates
+/// are compiled as part of their body, so do not appear in the plan.  Specs
+/// from other projects are assumed to be available already.
+pub struct ActionBuildPlan {
+    pub gprpath: PathBuf,
+}
+
+impl ActionBuildPlan {
+    /// The spec files of a unit, in the given scenario
+    fn specs_of_unit<'a>(
+        env: &'a Environment,
+        unit: NodeIndex,
+        scenario: &'a Scenario,
+    ) -> impl Iterator<Item = NodeIndex> + 'a {
+        env.graph
+            .0
+            .edges_directed(unit, Direction::Outgoing)
+            .filter(move |e| match e.weight() {
+                Edge::UnitSource((SourceKind::Spec, s)) => {
+                    !env.scenarios.never_matches(&(s & scenario))
+                }
+                _ => false,
+            })
+            .map(|e| e.target())
+    }
+
+    /// Build the graph of the files to compile and their dependencies
+    fn build_compile_graph(
+        env: &Environment,
+        gprnode: NodeIndex,
+        scenario: &Scenario,
+    ) -> Result<CompileGraph, Error> {
+        let mut graph = CompileGraph::new();
+        let mut map: HashMap<NodeIndex, CompileIndex> = HashMap::new();
+        for e in env.graph.0.edges_directed(gprnode, Direction::Outgoing) {
+            if let Edge::ProjectSource(s) = e.weight() {
+                let path = env.graph.get_source(e.target())?;
+                if env.scenarios.never_matches(&(s & scenario))
+                    || env.files[path].borrow().kind == SourceKind::Separate
+                {
+                    continue;
+                }
+                map.entry(e.target())
+                    .or_insert_with(|| graph.add_node(e.target()));
+            }
+        }
+
+        for (file, idx) in &map {
+            let mut needed: HashSet<NodeIndex> = HashSet::new();
+            for e in env.graph.0.edges_directed(*file, Direction::Outgoing) {
+                // A limited with only needs the limited view of the unit,
+                // which doesn't require compiling its spec first.
+                if let Edge::SourceImports(
+                    ImportKind::Private | ImportKind::Regular,
+                ) = e.weight()
+                {
+                    for unit in env.graph.imported_units(e.target()) {
+                        needed.extend(Self::specs_of_unit(env, unit, scenario));
+                    }
+                }
+            }
+
+            // A body also needs its own spec
+            for e in env.graph.0.edges_directed(*file, Direction::Incoming) {
+                if let Edge::UnitSource((SourceKind::Implementation, _)) =
+                    e.weight()
+                {
+                    needed.extend(Self::specs_of_unit(
+                        env,
+                        e.source(),
+                        scenario,
+                    ));
+                }
+            }
+
+            for spec in needed {
+                if spec != *file {
+                    if let Some(specidx) = map.get(&spec) {
+                        graph.add_edge(*specidx, *idx, ());
+                    }
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// The files to compile in each wave, and the files on the critical
+    /// path, one per wave.  Files that depend on each other (only possible
+    /// in invalid code, since limited withs are ignored) are reported as an
+    /// error.
+    fn plan<'a>(
+        env: &'a Environment,
+        gprnode: NodeIndex,
+        scenario: &Scenario,
+    ) -> Result<(FileGroups<'a>, Vec<&'a PathBuf>), Error> {
+        let graph = Self::build_compile_graph(env, gprnode, scenario)?;
+
+        // The components are returned in reverse topological order
+        let mut sorted = Vec::new();
+        for component in tarjan_scc(&graph) {
+            if component.len() > 1 {
+                let mut files = component
+                    .iter()
+                    .map(|n| env.graph.get_source(graph[*n]))
+                    .collect::<Result<Vec<_>, _>>()?;
+                files.sort();
+                return Err(Error::SourceCycle(files[0].clone()));
+            }
+            sorted.push(component[0]);
+        }
+        sorted.reverse();
+
+        // The wave for each node, and the predecessor on the longest path
+        let mut wave: HashMap<CompileIndex, usize> = HashMap::new();
+        let mut pred: HashMap<CompileIndex, CompileIndex> = HashMap::new();
+        for n in &sorted {
+            let mut w = 0;
+            for e in graph.edges_directed(*n, Direction::Incoming) {
+                if wave[&e.source()] + 1 > w {
+                    w = wave[&e.source()] + 1;
+                    pred.insert(*n, e.source());
+                }
+            }
+            wave.insert(*n, w);
+        }
+
+        let count = wave.values().max().map(|w| w + 1).unwrap_or(0);
+        let mut waves = vec![Vec::new(); count];
+        for n in &sorted {
+            waves[wave[n]].push(env.graph.get_source(graph[*n])?);
+        }
+        for files in &mut waves {
+            files.sort();
+        }
+
+        // Rebuild the critical path, from its last file
+        let mut path = Vec::new();
+        let mut current = sorted.iter().max_by_key(|n| wave[*n]).copied();
+        while let Some(n) = current {
+            path.push(env.graph.get_source(graph[n])?);
+            current = pred.get(&n).copied();
+        }
+        path.reverse();
+        Ok((waves, path))
+    }
+
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        let gpr = env
+            .get_gpr(&self.gprpath)
+            .ok_or(Error::NotFound("Project not found in graph".into()))?;
+        let scenario = settings.cli_scenario(&env.scenarios)?;
+        if scenario != Scenario::default() {
+            println!("Limit result to {}", env.scenarios.describe(&scenario));
+        }
+
+        let (waves, path) = Self::plan(env, gpr.node, &scenario)?;
+        for (w, files) in waves.iter().enumerate() {
+            settings.print_files(
+                &format!("Wave {} ({} files)", w + 1, files.len()),
+                files.clone(),
+                false,
+            );
+        }
+        println!("Critical path: {} waves", path.len());
+        for file in path {
+            println!("   {}", settings.display_path(file));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_build_plan::ActionBuildPlan;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+    use crate::scenarios::Scenario;
+
+    #[test]
+    fn waves() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "build_plan",
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("a.ads", "package A is end A;"),
+                ("a.adb", "package body A is end A;"),
+                ("b.ads", "with A; package B is end B;"),
+                ("c.adb", "with B; procedure C is begin null; end C;"),
+            ],
+        )?;
+        let gpr = &env.gprs[&settings.relto.join("p.gpr")];
+        let (waves, path) =
+            ActionBuildPlan::plan(&env, gpr.node, &Scenario::default())?;
+        let waves: Vec<String> = waves
+            .iter()
+            .map(|w| {
+                itertools::join(w.iter().map(|p| settings.display_path(p)), " ")
+            })
+            .collect();
+        let path: Vec<String> = path
+            .iter()
+            .map(|p| settings.display_path(p).to_string())
+            .collect();
+
+        // Each wave only needs specs from earlier waves: C needs the spec of
+        // B, which itself needs the spec of A.
+        assert_eq!(waves, vec!["a.ads", "a.adb b.ads", "c.adb"]);
+        assert_eq!(path, vec!["a.ads", "b.ads", "c.adb"]);
+        Ok(())
+    }
+
+    #[test]
+    fn cycles() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "build_plan_cycles",
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("a.ads", "with B; package A is end A;"),
+                ("b.ads", "with A; package B is end B;"),
+            ],
+        )?;
+        let gpr = &env.gprs[&settings.relto.join("p.gpr")];
+        let err = ActionBuildPlan::plan(&env, gpr.node, &Scenario::default())
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::SourceCycle(p) if p.ends_with("a.ads")),
+            "unexpected {:?}",
+            err
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,17 @@
 use crate::{
-    environment::Environment, errors::Error, graph::NodeIndex,
-    settings::Settings, sourcefile::SourceFile,
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, Node, NodeIndex},
+    settings::Settings,
+    sourcefile::{ImportKind, SourceFile, SourceKind},
+};
+use itertools::join;
+use petgraph::{
+    algo::{condensation, tarjan_scc},
+    graph::Graph,
+    visit::EdgeRef,
+    Directed, Direction,
 };
-use petgraph::{algo::condensation, graph::Graph, Directed, Direction};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
@@ -72,15 +81,15 @@ impl ActionCheck {
                 }
             })
             .collect();
-        let ada_unit_nodes: HashSet<NodeIndex> = env
+        let unit_nodes: HashSet<NodeIndex> = env
             .files
             .values()
             .filter_map(|file| {
                 let sm = file.borrow();
-                if sm.lang != "ada" || sm.unit_node.is_none() {
-                    None
+                if matches!(sm.lang.as_str(), "ada" | "c" | "c++") {
+                    sm.unit_node
                 } else {
-                    Some(sm.unit_node.unwrap())
+                    None
                 }
             })
             .collect();
@@ -88,7 +97,7 @@ impl ActionCheck {
             .keepers(env)
             .filter_map(|file| file.borrow().unit_node)
             .collect();
-        let unit_graph = self.build_unit_graph(env, &ada_unit_nodes);
+        let unit_graph = self.build_unit_graph(env, &unit_nodes);
         let condensed: CondensedGraph = condensation(unit_graph, true);
         let unused_nodes =
             self.find_unused(condensed, &keepers, &expected_nodes);
@@ -100,12 +109,12 @@ impl ActionCheck {
             self.quiet,
         );
         settings.print_files(
-            "Unused Ada files (not in unused.txt)",
+            "Unused source files (not in unused.txt)",
             paths.difference(&expected).collect(),
             self.quiet,
         );
         settings.print_files(
-            "Used Ada files but in unused.txt",
+            "Used source files but in unused.txt",
             expected.difference(&paths).collect(),
             self.quiet,
         );
@@ -224,6 +233,182 @@ impl ActionCheck {
         }
     }
 
+    /// Report imports of units that are found in several of the projects
+    /// visible from the importing file.
+    pub fn ambiguous(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        settings.print_lines(
+            "Ambiguous imports",
+            env.ambiguous_imports
+                .iter()
+                .map(|amb| {
+                    format!(
+                        "{} imports {}, found in {}",
+                        settings.display_path(&amb.source),
+                        amb.unit,
+                        join(
+                            amb.projects
+                                .iter()
+                                .map(|p| settings.display_path(p)),
+                            ", "
+                        ),
+                    )
+                })
+                .collect(),
+            self.quiet,
+        );
+        Ok(())
+    }
+
+    /// Report imports of units from projects that are not withed, directly
+    /// or not, by the project of the importing file.  gprbuild would only
+    /// report those when building the corresponding scenario.
+    pub fn bypassed_withs(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        settings.print_lines(
+            "Imports from projects not withed",
+            ActionCheck::find_bypassed_withs(env, settings)?,
+            self.quiet,
+        );
+        Ok(())
+    }
+
+    /// One line for each import of a unit from a project that is not
+    /// visible from the importing file.
+    fn find_bypassed_withs(
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<Vec<String>, Error> {
+        let mut visible: HashMap<NodeIndex, HashSet<NodeIndex>> =
+            HashMap::new();
+        let mut lines = Vec::new();
+
+        for e in env.graph.0.edge_references() {
+            if !matches!(e.weight(), Edge::SourceImports(_)) {
+                continue;
+            }
+            let unit = match &env.graph.0[e.target()] {
+                Node::Unit(unit) => unit.to_string(),
+                Node::Source(header) => {
+                    settings.display_path(header).to_string()
+                }
+                Node::Project(_) => continue,
+            };
+            let importers = env.graph.projects_of_source(e.source());
+            let owners = env.graph.projects_of_import(e.target());
+            if owners.is_empty() {
+                continue; // unit not found in any project
+            }
+            for importer in &importers {
+                let vis = visible
+                    .entry(*importer)
+                    .or_insert_with(|| env.graph.visible_projects(*importer));
+                if !owners.iter().any(|o| vis.contains(o)) {
+                    lines.push(format!(
+                        "{} imports {} from {}, not withed by {}",
+                        settings
+                            .display_path(env.graph.get_source(e.source())?),
+                        unit,
+                        settings
+                            .display_path(env.graph.get_project(owners[0])?),
+                        settings
+                            .display_path(env.graph.get_project(*importer)?),
+                    ));
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Report cycles between specs that do not go through a `limited with`.
+    /// Those are illegal in Ada, and are in general better detected before
+    /// building all the projects.
+    pub fn spec_cycles(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        settings.print_lines(
+            "Cycles between specs without a limited with",
+            ActionCheck::find_spec_cycles(env, settings)?,
+            self.quiet,
+        );
+        Ok(())
+    }
+
+    /// One line for each cycle, listing the specs it goes through.
+    fn find_spec_cycles(
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<Vec<String>, Error> {
+        let specs_of_unit = |unit: NodeIndex| {
+            env.graph
+                .0
+                .edges_directed(unit, Direction::Outgoing)
+                .filter(|e| {
+                    matches!(
+                        e.weight(),
+                        Edge::UnitSource((SourceKind::Spec, _))
+                    )
+                })
+                .map(|e| e.target())
+        };
+
+        let mut graph: Graph<NodeIndex, (), Directed, u32> = Graph::new();
+        let mut map: HashMap<NodeIndex, petgraph::graph::NodeIndex<u32>> =
+            HashMap::new();
+        for unit in env.graph.0.node_indices() {
+            if !matches!(env.graph.0[unit], Node::Unit(_)) {
+                continue;
+            }
+            for spec in specs_of_unit(unit) {
+                let from =
+                    *map.entry(spec).or_insert_with(|| graph.add_node(spec));
+                for e in env.graph.0.edges_directed(spec, Direction::Outgoing) {
+                    if let Edge::SourceImports(
+                        ImportKind::Private | ImportKind::Regular,
+                    ) = e.weight()
+                    {
+                        for target in env
+                            .graph
+                            .imported_units(e.target())
+                            .into_iter()
+                            .flat_map(specs_of_unit)
+                        {
+                            let to = *map
+                                .entry(target)
+                                .or_insert_with(|| graph.add_node(target));
+                            graph.add_edge(from, to, ());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        for scc in tarjan_scc(&graph) {
+            if scc.len() > 1 {
+                let mut paths: Vec<String> = scc
+                    .iter()
+                    .map(|n| {
+                        env.graph
+                            .get_source(graph[*n])
+                            .map(|p| settings.display_path(p).to_string())
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                paths.sort();
+                lines.push(paths.join(", "));
+            }
+        }
+        Ok(lines)
+    }
+
     /// Look for duplicate filenames.
     /// In general, those create ambiguities, so are better avoided.
     /// However, it is sometimes necessary, for instance when the body of an
@@ -276,3 +461,56 @@ impl ActionCheck {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::action_check::ActionCheck;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+
+    #[test]
+    fn bypassed_withs() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "bypassed_withs",
+            &[
+                ("v/v.gpr", "project V is end V;"),
+                ("v/utils.ads", "package Utils is end Utils;"),
+                ("w/w.gpr", "with \"../v/v\"; project W is end W;"),
+                ("w/w.adb", "with Utils; procedure W is begin null; end W;"),
+                ("a/a.gpr", "with \"../w/w\"; project A is end A;"),
+                ("a/a.adb", "with Utils; procedure A is begin null; end A;"),
+                ("b/b.gpr", "project B is end B;"),
+                ("b/b.adb", "with Utils; procedure B is begin null; end B;"),
+            ],
+        )?;
+
+        // W withs V directly, and V is visible from A through W.  Only B
+        // uses Utils without withing V.
+        assert_eq!(
+            ActionCheck::find_bypassed_withs(&env, &settings)?,
+            vec!["b/b.adb imports utils from v/v.gpr, not withed by b/b.gpr"],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn spec_cycles() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "spec_cycles",
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("a.ads", "with B; package A is end A;"),
+                ("b.ads", "private with A; package B is end B;"),
+                ("c.ads", "limited with D; package C is end C;"),
+                ("d.ads", "with C; package D is end D;"),
+            ],
+        )?;
+
+        // The cycle between C and D goes through a limited with
+        assert_eq!(
+            ActionCheck::find_spec_cycles(&env, &settings)?,
+            vec!["a.ads, b.ads"],
+        );
+        Ok(())
+    }
+}
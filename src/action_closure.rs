@@ -0,0 +1,190 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, NodeIndex},
+    scenarios::Scenario,
+    settings::Settings,
+    sourcefile::{ImportKind, SourceKind},
+};
+use petgraph::{visit::EdgeRef, Direction};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Report all source files needed to build a main unit, like gnatbind would.
+/// This includes the spec of every imported unit, and the body and separates
+/// of every unit whose spec is needed.  Parent units are needed too, which
+/// is already taken care of by `SourceImports` edges.  Units that are only
+/// imported through a `limited with` are not part of the closure.
+pub struct ActionClosure {
+    pub path: PathBuf,
+}
+
+impl ActionClosure {
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        let for_scenario = settings.cli_scenario(&env.scenarios)?;
+        if for_scenario != Scenario::default() {
+            println!(
+                "Limit result to {}",
+                env.scenarios.describe(&for_scenario)
+            );
+        }
+        settings.print_lines(
+            &format!("Closure of {}", settings.display_path(&self.path)),
+            self.closure(env, settings, &for_scenario)?,
+            false,
+        );
+        Ok(())
+    }
+
+    /// One line for each file in the closure, with its kind and the
+    /// scenarios in which it is needed.
+    fn closure(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+        for_scenario: &Scenario,
+    ) -> Result<Vec<String>, Error> {
+        let info = env
+            .files
+            .get(&self.path)
+            .ok_or(Error::NotFound("File not found in graph".into()))?
+            .clone();
+        let main = info.borrow();
+
+        // For each file in the closure, its kind and the scenarios in which
+        // it is needed.  A file might be reached in several scenarios that
+        // cannot be combined.
+        let mut closure: HashMap<NodeIndex, (SourceKind, Vec<Scenario>)> =
+            HashMap::new();
+        let mut tovisit: Vec<(NodeIndex, Scenario)> = Vec::new();
+
+        // Add all source files of a unit, in the given scenario
+        let mut add_unit =
+            |unit: NodeIndex,
+             context: &Scenario,
+             tovisit: &mut Vec<(NodeIndex, Scenario)>| {
+                for e in env.graph.0.edges_directed(unit, Direction::Outgoing) {
+                    if let Edge::UnitSource((kind, s)) = e.weight() {
+                        let s = context & s;
+                        if env.scenarios.never_matches(&s) {
+                            continue;
+                        }
+                        let entry = closure
+                            .entry(e.target())
+                            .or_insert((*kind, vec![]));
+                        if !entry.1.iter().any(|known| (&s & known) == s) {
+                            entry.1.push(s.clone());
+                            tovisit.push((e.target(), s));
+                        }
+                    }
+                }
+            };
+
+        let main_units: Vec<NodeIndex> = env
+            .graph
+            .0
+            .edges_directed(main.file_node, Direction::Incoming)
+            .filter(|e| matches!(e.weight(), Edge::UnitSource(_)))
+            .map(|e| e.source())
+            .collect();
+        for unit in main_units {
+            add_unit(unit, for_scenario, &mut tovisit);
+        }
+
+        while let Some((file, context)) = tovisit.pop() {
+            for e in env.graph.0.edges_directed(file, Direction::Outgoing) {
+                match e.weight() {
+                    Edge::SourceImports(ImportKind::Limited) => {}
+                    Edge::SourceImports(_) => {
+                        for unit in env.graph.imported_units(e.target()) {
+                            add_unit(unit, &context, &mut tovisit);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let lines: Vec<String> = closure
+            .iter()
+            .filter_map(|(node, (kind, scenarios))| {
+                let path = env.graph.get_source(*node).ok()?;
+                let kind = match kind {
+                    SourceKind::Spec => "spec",
+                    SourceKind::Implementation => "body",
+                    SourceKind::Separate => "separate",
+                };
+                let scenarios: Vec<String> = scenarios
+                    .iter()
+                    .map(|s| env.scenarios.describe(s))
+                    .collect();
+                Some(if scenarios.iter().any(|s| s == "*") {
+                    format!("{} ({})", settings.display_path(path), kind)
+                } else {
+                    format!(
+                        "{} ({}) when {}",
+                        settings.display_path(path),
+                        kind,
+                        scenarios.join(" | "),
+                    )
+                })
+            })
+            .collect();
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_closure::ActionClosure;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+    use crate::scenarios::Scenario;
+
+    #[test]
+    fn closure() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "closure",
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("main.adb", "with A.B; procedure Main is begin null; end;"),
+                ("a.ads", "package A is end A;"),
+                ("a-b.ads", "with C; package A.B is end A.B;"),
+                ("a-b.adb", "package body A.B is end A.B;"),
+                ("c.ads", "limited with L; package C is end C;"),
+                ("c.adb", "with D; package body C is end C;"),
+                ("d.ads", "package D is end D;"),
+                ("l.ads", "package L is end L;"),
+                ("l.adb", "package body L is end L;"),
+                ("unused.ads", "package Unused is end Unused;"),
+            ],
+        )?;
+        let action = ActionClosure {
+            path: settings.relto.join("main.adb"),
+        };
+        let mut lines =
+            action.closure(&env, &settings, &Scenario::default())?;
+        lines.sort();
+
+        // The parent unit A is needed, as well as the specs needed by the
+        // bodies.  L is only reached through a limited with.  A subprogram
+        // body without a spec also acts as the spec of the unit.
+        assert_eq!(
+            lines,
+            vec![
+                "a-b.adb (body)",
+                "a-b.ads (spec)",
+                "a.ads (spec)",
+                "c.adb (body)",
+                "c.ads (spec)",
+                "d.ads (spec)",
+                "main.adb (spec)",
+            ],
+        );
+        Ok(())
+    }
+}
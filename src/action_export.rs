@@ -0,0 +1,426 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, Node},
+    settings::Settings,
+    sourcefile::{ImportKind, SourceKind},
+};
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dot,
+    GraphML,
+    Json,
+    Mermaid,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum NodeKind {
+    Project,
+    Unit,
+    Source,
+}
+
+impl NodeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Project => "project",
+            NodeKind::Unit => "unit",
+            NodeKind::Source => "source",
+        }
+    }
+}
+
+/// A node, as it will be output
+#[derive(Serialize)]
+struct ExportNode {
+    id: usize,
+    kind: NodeKind,
+    label: String,
+}
+
+/// An edge, as it will be output
+#[derive(Serialize)]
+struct ExportEdge {
+    source: usize,
+    target: usize,
+    kind: &'static str,
+    scenario: Option<String>,
+}
+
+/// The whole graph, as output in JSON
+#[derive(Serialize)]
+struct ExportGraph<'a> {
+    nodes: &'a [ExportNode],
+    edges: &'a [ExportEdge],
+}
+
+pub struct ActionExport {
+    pub format: ExportFormat,
+
+    // Which levels of the graph to output.  Edges are only kept when both
+    // ends are kept.
+    pub projects: bool,
+    pub units: bool,
+    pub sources: bool,
+}
+
+impl ActionExport {
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        print!("{}", self.export(env, settings)?);
+        Ok(())
+    }
+
+    /// The graph, in the requested format
+    fn export(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<String, Error> {
+        let graph = &env.graph.0;
+        let mut kept = HashSet::new();
+        let mut nodes = Vec::new();
+        for idx in graph.node_indices() {
+            let (kind, label, keep) = match &graph[idx] {
+                Node::Project(p) => (
+                    NodeKind::Project,
+                    settings.display_path(p).to_string(),
+                    self.projects,
+                ),
+                Node::Unit(u) => (NodeKind::Unit, u.to_string(), self.units),
+                Node::Source(s) => (
+                    NodeKind::Source,
+                    settings.display_path(s).to_string(),
+                    self.sources,
+                ),
+            };
+            if keep {
+                kept.insert(idx);
+                nodes.push(ExportNode {
+                    id: idx.index(),
+                    kind,
+                    label,
+                });
+            }
+        }
+
+        let edges: Vec<ExportEdge> = graph
+            .edge_references()
+            .filter(|e| {
+                kept.contains(&e.source()) && kept.contains(&e.target())
+            })
+            .map(|e| {
+                let (kind, scenario) = match e.weight() {
+                    Edge::GPRExtends => ("extends", None),
+                    Edge::GPRImports => ("imports", None),
+                    Edge::GPRLimitedImports => ("limited_imports", None),
+                    Edge::ProjectSource(s) => {
+                        ("project_source", Some(env.scenarios.describe(s)))
+                    }
+                    Edge::UnitSource((k, s)) => (
+                        match k {
+                            SourceKind::Spec => "unit_spec",
+                            SourceKind::Implementation => "unit_implementation",
+                            SourceKind::Separate => "unit_separate",
+                        },
+                        Some(env.scenarios.describe(s)),
+                    ),
+                    Edge::SourceImports(k) => (
+                        match k {
+                            ImportKind::Limited => "source_limited_imports",
+                            ImportKind::Private => "source_private_imports",
+                            ImportKind::Regular => "source_imports",
+                        },
+                        None,
+                    ),
+                };
+                ExportEdge {
+                    source: e.source().index(),
+                    target: e.target().index(),
+                    kind,
+                    scenario,
+                }
+            })
+            .collect();
+
+        Ok(match self.format {
+            ExportFormat::Dot => to_dot(&nodes, &edges),
+            ExportFormat::GraphML => to_graphml(&nodes, &edges),
+            ExportFormat::Json => to_json(&nodes, &edges)?,
+            ExportFormat::Mermaid => to_mermaid(&nodes, &edges),
+        })
+    }
+}
+
+/// Quote a string for DOT.
+fn quoted(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_dot(nodes: &[ExportNode], edges: &[ExportEdge]) -> String {
+    let mut out = String::from("digraph gprdeps {\n");
+    for n in nodes {
+        let shape = match n.kind {
+            NodeKind::Project => "box",
+            NodeKind::Unit => "ellipse",
+            NodeKind::Source => "note",
+        };
+        let _ = writeln!(
+            out,
+            "  n{} [label={}, kind={}, shape={}];",
+            n.id,
+            quoted(&n.label),
+            n.kind.as_str(),
+            shape,
+        );
+    }
+    for e in edges {
+        let label = match &e.scenario {
+            None => e.kind.to_string(),
+            Some(s) => format!("{}\n{}", e.kind, s),
+        };
+        let _ = writeln!(
+            out,
+            "  n{} -> n{} [label={}, kind={}];",
+            e.source,
+            e.target,
+            quoted(&label),
+            e.kind,
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn to_graphml(nodes: &[ExportNode], edges: &[ExportEdge]) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+         <key id=\"kind\" for=\"all\" attr.name=\"kind\" attr.type=\"string\"/>\n  \
+         <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n  \
+         <key id=\"scenario\" for=\"edge\" attr.name=\"scenario\" attr.type=\"string\"/>\n  \
+         <graph id=\"gprdeps\" edgedefault=\"directed\">\n",
+    );
+    for n in nodes {
+        let _ = writeln!(
+            out,
+            "    <node id=\"n{}\"><data key=\"kind\">{}</data>\
+             <data key=\"label\">{}</data></node>",
+            n.id,
+            n.kind.as_str(),
+            xml_escape(&n.label),
+        );
+    }
+    for e in edges {
+        let _ = write!(
+            out,
+            "    <edge source=\"n{}\" target=\"n{}\">\
+             <data key=\"kind\">{}</data>",
+            e.source, e.target, e.kind,
+        );
+        if let Some(s) = &e.scenario {
+            let _ =
+                write!(out, "<data key=\"scenario\">{}</data>", xml_escape(s));
+        }
+        out.push_str("</edge>\n");
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn to_json(
+    nodes: &[ExportNode],
+    edges: &[ExportEdge],
+) -> Result<String, Error> {
+    let mut out = serde_json::to_string_pretty(&ExportGraph { nodes, edges })?;
+    out.push('\n');
+    Ok(out)
+}
+
+fn to_mermaid(nodes: &[ExportNode], edges: &[ExportEdge]) -> String {
+    // Mermaid does not support backslash escapes in labels, only entities
+    let escape = |s: &str| s.replace('"', "#quot;");
+    let mut out = String::from("graph LR\n");
+    for n in nodes {
+        let _ = match n.kind {
+            NodeKind::Project => {
+                writeln!(out, "  n{}[\"{}\"]", n.id, escape(&n.label))
+            }
+            NodeKind::Unit => {
+                writeln!(out, "  n{}([\"{}\"])", n.id, escape(&n.label))
+            }
+            NodeKind::Source => {
+                writeln!(out, "  n{}[/\"{}\"/]", n.id, escape(&n.label))
+            }
+        };
+    }
+    for e in edges {
+        let label = match &e.scenario {
+            Some(s) if s != "*" => format!("{} {}", e.kind, s),
+            _ => e.kind.to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "  n{} -->|\"{}\"| n{}",
+            e.source,
+            escape(&label),
+            e.target,
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quoted, ActionExport, ExportFormat};
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+
+    #[test]
+    fn test_quoted() {
+        assert_eq!(quoted("a/b.gpr"), "\"a/b.gpr\"");
+        assert_eq!(quoted("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+        assert_eq!(quoted("\x01"), "\"\\u0001\"");
+    }
+
+    /// Export the sources and units of a small tree.  Returns the graph and
+    /// the node ids of a.ads, b.ads and unit a.
+    fn export(
+        name: &str,
+        format: ExportFormat,
+    ) -> Result<(String, [usize; 3]), Error> {
+        let (env, settings, _dir) = parse_tree(
+            name,
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("a.ads", "package A is end A;"),
+                ("b.ads", "with A; package B is end B;"),
+            ],
+        )?;
+        let action = ActionExport {
+            format,
+            projects: false,
+            units: true,
+            sources: true,
+        };
+        let out = action.export(&env, &settings)?;
+        let file = |path: &str| {
+            env.files[&settings.relto.join(path)]
+                .borrow()
+                .file_node
+                .index()
+        };
+        let unit = env.files[&settings.relto.join("a.ads")]
+            .borrow()
+            .unit_node
+            .unwrap()
+            .index();
+        let ids = [file("a.ads"), file("b.ads"), unit];
+        Ok((out, ids))
+    }
+
+    #[test]
+    fn export_dot() -> Result<(), Error> {
+        let (out, [a, b, unit]) = export("export_dot", ExportFormat::Dot)?;
+        assert!(out.starts_with("digraph gprdeps {\n"));
+        assert!(out.contains(&format!(
+            "  n{} [label=\"a.ads\", kind=source, shape=note];\n",
+            a
+        )));
+        assert!(out.contains(&format!(
+            "  n{} [label=\"a\", kind=unit, shape=ellipse];\n",
+            unit
+        )));
+        assert!(out.contains(&format!(
+            "  n{} -> n{} [label=\"source_imports\", kind=source_imports];\n",
+            b, unit
+        )));
+        assert!(!out.contains("kind=project"));
+        Ok(())
+    }
+
+    #[test]
+    fn export_graphml() -> Result<(), Error> {
+        let (out, [a, b, unit]) =
+            export("export_graphml", ExportFormat::GraphML)?;
+        assert!(out.ends_with("  </graph>\n</graphml>\n"));
+        assert!(out.contains(&format!(
+            "    <node id=\"n{}\"><data key=\"kind\">source</data>\
+             <data key=\"label\">a.ads</data></node>\n",
+            a
+        )));
+        assert!(out.contains(&format!(
+            "    <edge source=\"n{}\" target=\"n{}\">\
+             <data key=\"kind\">source_imports</data></edge>\n",
+            b, unit
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn export_json() -> Result<(), Error> {
+        let (out, [a, b, unit]) = export("export_json", ExportFormat::Json)?;
+        let json: serde_json::Value = serde_json::from_str(&out)?;
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert!(nodes.contains(&serde_json::json!({
+            "id": a, "kind": "source", "label": "a.ads"
+        })));
+        assert!(nodes.contains(&serde_json::json!({
+            "id": unit, "kind": "unit", "label": "a"
+        })));
+        assert!(json["edges"].as_array().unwrap().contains(
+            &serde_json::json!({
+                "source": b,
+                "target": unit,
+                "kind": "source_imports",
+                "scenario": null,
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn export_mermaid() -> Result<(), Error> {
+        let (out, [a, b, unit]) =
+            export("export_mermaid", ExportFormat::Mermaid)?;
+        assert!(out.starts_with("graph LR\n"));
+        assert!(out.contains(&format!("  n{}[/\"a.ads\"/]\n", a)));
+        assert!(out.contains(&format!("  n{}([\"a\"])\n", unit)));
+        assert!(out.contains(&format!(
+            "  n{} -->|\"source_imports\"| n{}\n",
+            b, unit
+        )));
+        Ok(())
+    }
+}
@@ -37,8 +37,8 @@ impl ActionImported {
             .clone();
         let file = info.borrow();
 
-        let for_scenario = settings.cli_scenario(&env.scenarios)?;
-        if for_scenario != Scenario::default() {
+        let for_scenario = &settings.cli_scenario(&env.scenarios)?;
+        if *for_scenario != Scenario::default() {
             println!(
                 "Limit result to {}",
                 env.scenarios.describe(for_scenario)
@@ -49,9 +49,9 @@ impl ActionImported {
         let filtered =
             petgraph::visit::EdgeFiltered::from_fn(&env.graph.0, |e| {
                 match e.weight() {
-                    Edge::SourceImports => true,
+                    Edge::SourceImports(_) => true,
                     Edge::UnitSource((_, s)) => {
-                        !env.scenarios.never_matches(s & for_scenario)
+                        !env.scenarios.never_matches(&(s & for_scenario))
                     }
                     _ => false,
                 }
@@ -71,9 +71,16 @@ impl ActionImported {
                     env.graph
                         .0
                         .edges_directed(file.file_node, Direction::Outgoing)
-                        .filter(|e| matches!(e.weight(), Edge::SourceImports))
+                        .filter(|e| {
+                            matches!(e.weight(), Edge::SourceImports(_))
+                        })
                         .map(|e| e.target())
                         .flat_map(|unit| {
+                            // An include imports the header file itself
+                            let header = match &env.graph.0[unit] {
+                                Node::Source(path) => Some(path.clone()),
+                                _ => None,
+                            };
                             env.graph
                                 .0
                                 .edges_directed(unit, Direction::Outgoing)
@@ -81,7 +88,7 @@ impl ActionImported {
                                     Edge::UnitSource((_, s)) => {
                                         if env
                                             .scenarios
-                                            .never_matches(s & for_scenario)
+                                            .never_matches(&(s & for_scenario))
                                         {
                                             None
                                         } else {
@@ -95,6 +102,7 @@ impl ActionImported {
                                     }
                                     _ => None,
                                 })
+                                .chain(header)
                         })
                         .collect()
                 }
@@ -114,18 +122,21 @@ impl ActionImported {
                         .0
                         .edges_directed(file.file_node, Direction::Incoming)
                         .filter(|e| match e.weight() {
-                            Edge::UnitSource((_, s)) => {
-                                !env.scenarios.never_matches(s & for_scenario)
-                            }
+                            Edge::UnitSource((_, s)) => !env
+                                .scenarios
+                                .never_matches(&(s & for_scenario)),
                             _ => false,
                         })
                         .map(|e| e.source())
+                        // Files including it as a header import the file
+                        // itself rather than its unit.
+                        .chain(std::iter::once(file.file_node))
                         .flat_map(|unit| {
                             env.graph
                                 .0
                                 .edges_directed(unit, Direction::Incoming)
                                 .filter_map(move |e| match e.weight() {
-                                    Edge::SourceImports => {
+                                    Edge::SourceImports(_) => {
                                         match &env.graph.0[e.source()] {
                                             Node::Source(path) => {
                                                 Some(path.clone())
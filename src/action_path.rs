@@ -1,8 +1,9 @@
 use crate::{
     environment::Environment,
     errors::Error,
-    graph::{Node, NodeIndex},
+    graph::{Edge, Node, NodeIndex},
     settings::Settings,
+    sourcefile::ImportKind,
 };
 use petgraph::algo::astar;
 use std::path::{Path, PathBuf};
@@ -32,6 +33,19 @@ impl ActionPath {
         env: &Environment,
         settings: &Settings,
     ) -> Result<(), Error> {
+        for line in self.path_lines(env, settings)? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// One line for each node on the path from source to target.
+    fn path_lines(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<Vec<String>, Error> {
+        let mut lines = Vec::new();
         let source = ActionPath::find_node(env, &self.source)?;
         let target = ActionPath::find_node(env, &self.target)?;
         let path = astar(
@@ -44,24 +58,101 @@ impl ActionPath {
 
         match path {
             Some((_, path)) => {
-                for p in path {
-                    match &env.graph.0[p] {
+                // How the current unit was imported, if not a regular with
+                let mut via = "";
+                for (idx, p) in path.iter().enumerate() {
+                    if idx > 0 {
+                        if let Some(e) =
+                            env.graph.0.find_edge(path[idx - 1], *p)
+                        {
+                            match env.graph.0[e] {
+                                Edge::SourceImports(ImportKind::Limited) => {
+                                    via = " (limited with)";
+                                }
+                                Edge::SourceImports(ImportKind::Private) => {
+                                    via = " (private with)";
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    match &env.graph.0[*p] {
                         Node::Source(path) => {
-                            println!("file: {}", settings.display_path(path));
+                            lines.push(format!(
+                                "file: {}{}",
+                                settings.display_path(path),
+                                via
+                            ));
+                            via = "";
                         }
                         Node::Unit(qname) => {
                             if self.show_units {
-                                println!("unit: {}", qname);
+                                lines.push(format!("unit: {}{}", qname, via));
+                                via = "";
                             }
                         }
                         Node::Project(path) => {
-                            println!("gpr: {}", settings.display_path(path));
+                            lines.push(format!(
+                                "gpr: {}",
+                                settings.display_path(path)
+                            ));
                         }
                     }
                 }
             }
-            None => println!("There was no path"),
+            None => lines.push("There was no path".to_string()),
         }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_path::ActionPath;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+
+    #[test]
+    fn annotations() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "path_annotations",
+            &[
+                ("p.gpr", "project P is end P;"),
+                ("a.ads", "limited with B; package A is end A;"),
+                ("b.ads", "private with C; package B is end B;"),
+                ("c.ads", "package C is end C;"),
+            ],
+        )?;
+
+        let action = ActionPath {
+            source: settings.relto.join("a.ads"),
+            target: settings.relto.join("c.ads"),
+            show_units: true,
+        };
+        assert_eq!(
+            action.path_lines(&env, &settings)?,
+            vec![
+                "file: a.ads",
+                "unit: b (limited with)",
+                "file: b.ads",
+                "unit: c (private with)",
+                "file: c.ads",
+            ],
+        );
+
+        // Without units, the annotation is shown on the file
+        let action = ActionPath {
+            show_units: false,
+            ..action
+        };
+        assert_eq!(
+            action.path_lines(&env, &settings)?,
+            vec![
+                "file: a.ads",
+                "file: b.ads (limited with)",
+                "file: c.ads (private with)",
+            ],
+        );
         Ok(())
     }
 }
@@ -0,0 +1,144 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, NodeIndex},
+    settings::Settings,
+};
+use petgraph::{visit::EdgeRef, Direction};
+use std::collections::{HashMap, HashSet};
+
+pub struct ActionRedundantWiths {}
+
+impl ActionRedundantWiths {
+    pub fn new() -> Self {
+        ActionRedundantWiths {}
+    }
+
+    /// Compute the transitive reduction of the project imports: a with
+    /// clause from A to C is redundant when C is also imported through
+    /// another with clause of A (for instance A withs B, which withs C).
+    /// Such a with can only be dropped if the sources of A do not use units
+    /// from C directly.
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        let (can_drop, must_stay) = self.redundant_withs(env, settings)?;
+        settings.print_lines(
+            "Redundant withs that can be dropped",
+            can_drop,
+            false,
+        );
+        settings.print_lines(
+            "Redundant withs whose units are used directly",
+            must_stay,
+            false,
+        );
+        Ok(())
+    }
+
+    /// The redundant with clauses, split between those that can be dropped
+    /// and those whose units are used by the sources.
+    fn redundant_withs(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(Vec<String>, Vec<String>), Error> {
+        let mut imported: HashMap<NodeIndex, HashSet<NodeIndex>> =
+            HashMap::new();
+        let mut can_drop = Vec::new();
+        let mut must_stay = Vec::new();
+
+        for gpr in env.gprs.values() {
+            let withs: Vec<NodeIndex> = env
+                .graph
+                .0
+                .edges_directed(gpr.node, Direction::Outgoing)
+                .filter(|e| matches!(e.weight(), Edge::GPRImports))
+                .map(|e| e.target())
+                .filter(|w| !env.is_implicit_project(*w))
+                .collect();
+            let used = env.graph.projects_used_by_sources(gpr.node);
+
+            for w in &withs {
+                let through = withs.iter().find(|other| {
+                    *other != w
+                        && imported
+                            .entry(**other)
+                            .or_insert_with(|| {
+                                env.graph.imported_projects(**other)
+                            })
+                            .contains(w)
+                });
+                if let Some(through) = through {
+                    let gprpath = env.graph.get_project(gpr.node)?;
+                    let wpath = env.graph.get_project(*w)?;
+                    let Some(withline) = gpr.dep_lines.get(wpath) else {
+                        return Err(Error::WithPath {
+                            path: gprpath.clone(),
+                            error: Box::new(Error::not_found(format!(
+                                "with clause for {}",
+                                wpath.display()
+                            ))),
+                        });
+                    };
+                    let line = format!(
+                        "{}:{} with {} (through {})",
+                        settings.display_path(gprpath),
+                        withline,
+                        settings.display_path(wpath),
+                        settings.display_path(env.graph.get_project(*through)?),
+                    );
+                    if used.contains(w) {
+                        must_stay.push(line);
+                    } else {
+                        can_drop.push(line);
+                    }
+                }
+            }
+        }
+
+        Ok((can_drop, must_stay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_redundant_withs::ActionRedundantWiths;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+
+    #[test]
+    fn redundant_withs() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "redundant_withs",
+            &[
+                ("c/c.gpr", "project C is end C;"),
+                ("c/utils.ads", "package Utils is end Utils;"),
+                ("b/b.gpr", "with \"../c/c\"; project B is end B;"),
+                ("b/b.ads", "package B is end B;"),
+                (
+                    "a/a.gpr",
+                    "with \"../b/b\";\nwith \"../c/c\";\nproject A is end A;",
+                ),
+                ("a/a.adb", "with B; procedure A is begin null; end A;"),
+                (
+                    "d/d.gpr",
+                    "with \"../b/b\";\nwith \"../c/c\";\nproject D is end D;",
+                ),
+                ("d/d.adb", "with Utils; procedure D is begin null; end D;"),
+                ("e/e.gpr", "with \"../c/c\"; project E is end E;"),
+                ("e/e.adb", "with Utils; procedure E is begin null; end E;"),
+            ],
+        )?;
+
+        // B withs C, so the with of C in A and D is redundant.  Only A can
+        // drop it, since D uses Utils directly.  E has a single with.
+        let (can_drop, must_stay) =
+            ActionRedundantWiths::new().redundant_withs(&env, &settings)?;
+        assert_eq!(can_drop, vec!["a/a.gpr:2 with c/c.gpr (through b/b.gpr)"]);
+        assert_eq!(must_stay, vec!["d/d.gpr:2 with c/c.gpr (through b/b.gpr)"]);
+        Ok(())
+    }
+}
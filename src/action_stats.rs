@@ -11,7 +11,7 @@ impl ActionStats {
     pub fn perform(
         &self,
         env: &Environment,
-        _settings: &Settings,
+        settings: &Settings,
     ) -> Result<(), Error> {
         env.scenarios.print_stats();
 
@@ -25,9 +25,23 @@ impl ActionStats {
 
         println!("\nGraph nodes:  {:-7}", env.graph.node_count());
         println!("   Projects:     = {:-6}", env.gprs.len());
-        println!("   Units:        + {:-6}", env.units.len());
+        if settings.cache.is_some() {
+            println!("      Evaluated:     {:-6}", env.cache.gprs_evaluated);
+            println!("      From cache:    {:-6}", env.cache.gprs_reused);
+        }
+        println!(
+            "   Units:        + {:-6}",
+            env.units.values().map(Vec::len).sum::<usize>()
+        );
         println!("   Source files: + {:-6}", env.files.len());
+        if settings.cache.is_some() {
+            println!("      Parsed:        {:-6}", env.cache.parsed);
+            println!("      From cache:    {:-6}", env.cache.reused);
+        }
         println!("Graph edges:  {:-7}", env.graph.edge_count());
+        if env.merged_edges > 0 {
+            println!("   Merged:       - {:-6}", env.merged_edges);
+        }
         Ok(())
     }
 }
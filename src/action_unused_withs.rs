@@ -0,0 +1,174 @@
+use crate::{
+    environment::Environment,
+    errors::Error,
+    graph::{Edge, NodeIndex},
+    settings::Settings,
+};
+use petgraph::{visit::EdgeRef, Direction};
+use std::collections::{HashMap, HashSet};
+
+pub struct ActionUnusedWiths {}
+
+impl ActionUnusedWiths {
+    pub fn new() -> Self {
+        ActionUnusedWiths {}
+    }
+
+    /// Report the "with" clauses of each project that none of its sources
+    /// needs, in any scenario.
+    /// A with is needed when one of the sources imports a unit from the
+    /// withed project, or from one of the projects it imports in turn (we
+    /// might not have a direct with for those).  It is also needed when the
+    /// project file itself references the withed project, for instance
+    /// Shared'Source_Dirs.
+    /// A limited with is only there to break a cycle between projects, but
+    /// it is still needed only when the sources use the withed project, so
+    /// it is reported like the other withs.
+    pub fn perform(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<(), Error> {
+        settings.print_lines(
+            "Unused withs",
+            self.unused_withs(env, settings)?,
+            false,
+        );
+        Ok(())
+    }
+
+    /// One line for each unneeded with clause.
+    fn unused_withs(
+        &self,
+        env: &Environment,
+        settings: &Settings,
+    ) -> Result<Vec<String>, Error> {
+        let mut visible: HashMap<NodeIndex, HashSet<NodeIndex>> =
+            HashMap::new();
+        let mut lines = Vec::new();
+
+        for gpr in env.gprs.values() {
+            let referenced = &gpr.referenced_gprs;
+            let mut limited: HashSet<NodeIndex> = HashSet::new();
+            let withs: Vec<NodeIndex> = env
+                .graph
+                .0
+                .edges_directed(gpr.node, Direction::Outgoing)
+                .filter_map(|e| match e.weight() {
+                    Edge::GPRImports => Some(e.target()),
+                    Edge::GPRLimitedImports => {
+                        limited.insert(e.target());
+                        Some(e.target())
+                    }
+                    _ => None,
+                })
+                .filter(|w| !env.is_implicit_project(*w))
+                .collect();
+
+            // A project we have a direct with for only justifies that with.
+            // Otherwise, we do not know which of the withs gives access to
+            // it, so they are all needed.
+            let mut needed: HashSet<NodeIndex> =
+                referenced.iter().copied().collect();
+            for used in env.graph.projects_used_by_sources(gpr.node) {
+                if withs.contains(&used) {
+                    needed.insert(used);
+                } else {
+                    for w in &withs {
+                        if visible
+                            .entry(*w)
+                            .or_insert_with(|| env.graph.visible_projects(*w))
+                            .contains(&used)
+                        {
+                            needed.insert(*w);
+                        }
+                    }
+                }
+            }
+
+            for w in withs {
+                if !needed.contains(&w) {
+                    lines.push(format!(
+                        "{}: {}with {}",
+                        settings.display_path(env.graph.get_project(gpr.node)?),
+                        if limited.contains(&w) { "limited " } else { "" },
+                        settings.display_path(env.graph.get_project(w)?),
+                    ));
+                }
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action_unused_withs::ActionUnusedWiths;
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+
+    #[test]
+    fn unused_withs() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "unused_withs",
+            &[
+                ("used/used.gpr", "project Used is end Used;"),
+                ("used/utils.ads", "package Utils is end Utils;"),
+                ("unused/unused.gpr", "project Unused is end Unused;"),
+                ("unused/other.ads", "package Other is end Other;"),
+                (
+                    "shared/shared.gpr",
+                    "project Shared is
+                        for Source_Dirs use (\"src\");
+                     end Shared;",
+                ),
+                ("shared/src/common.ads", "package Common is end Common;"),
+                (
+                    "a/a.gpr",
+                    "with \"../used/used\";
+                     with \"../unused/unused\";
+                     with \"../shared/shared\";
+                     project A is
+                        for Source_Dirs use (\".\") & Shared'Source_Dirs;
+                     end A;",
+                ),
+                ("a/a.adb", "with Utils; procedure A is begin null; end A;"),
+            ],
+        )?;
+
+        // Shared is only referenced by the project file, Used by the sources
+        assert_eq!(
+            ActionUnusedWiths::new().unused_withs(&env, &settings)?,
+            vec!["a/a.gpr: with unused/unused.gpr"],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unused_limited_withs() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "unused_limited_withs",
+            &[
+                ("b/b.gpr", "project B is end B;"),
+                ("b/utils.ads", "package Utils is end Utils;"),
+                ("c/c.gpr", "project C is end C;"),
+                ("c/other.ads", "package Other is end Other;"),
+                (
+                    "a/a.gpr",
+                    "limited with \"../b/b\";
+                     limited with \"../c/c\";
+                     project A is end A;",
+                ),
+                ("a/a.ads", "limited with Utils; package A is end A;"),
+            ],
+        )?;
+
+        // The sources of A only use B
+        assert_eq!(
+            ActionUnusedWiths::new().unused_withs(&env, &settings)?,
+            vec!["a/a.gpr: limited with c/c.gpr"],
+        );
+        Ok(())
+    }
+}
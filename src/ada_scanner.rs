@@ -3,7 +3,7 @@ use crate::{
     base_lexer::BaseScanner,
     errors::Error,
     qnames::QName,
-    sourcefile::{ParseResult, SourceKind},
+    sourcefile::{ImportKind, ParseResult, SourceKind},
     tokens::TokenKind,
 };
 use ustr::Ustr;
@@ -22,14 +22,20 @@ impl<'a> AdaScanner<'a> {
             unitname: QName::default(),
             kind: SourceKind::Spec,
             deps: Default::default(),
+            includes: Default::default(),
         };
 
+        // The "limited" and "private" keywords seen before the next "with"
+        let mut import = ImportKind::Regular;
+
         loop {
             let n = scan.base.safe_next()?;
             match n.kind {
                 TokenKind::Use
                 | TokenKind::With => {
-                    scan.parse_with_or_use_clause(n.kind, &mut info)
+                    let kind = std::mem::replace(
+                        &mut import, ImportKind::Regular);
+                    scan.parse_with_or_use_clause(n.kind, kind, &mut info)
                 }
                 TokenKind::Pragma => {
                     let name = scan.parse_pragma()?;
@@ -39,8 +45,14 @@ impl<'a> AdaScanner<'a> {
                     }
                     Ok(())
                 }
-                TokenKind::Limited    // limited with 
-                | TokenKind::Private => Ok(()),
+                TokenKind::Limited => {   // limited [private] with
+                    import = ImportKind::Limited;
+                    Ok(())
+                }
+                TokenKind::Private => {   // private with, or private package
+                    import = import.min(ImportKind::Private);
+                    Ok(())
+                }
                 TokenKind::Separate => {
                     match scan.parse_separate() {
                         Ok(sep) => {
@@ -94,6 +106,7 @@ impl<'a> AdaScanner<'a> {
     fn parse_with_or_use_clause(
         &mut self,
         kind: TokenKind,
+        import: ImportKind,
         info: &mut ParseResult,
     ) -> Result<(), Error> {
         if kind == TokenKind::Use && TokenKind::Type == self.base.peek() {
@@ -102,7 +115,7 @@ impl<'a> AdaScanner<'a> {
         loop {
             let d = self.base.expect_qname(TokenKind::Dot)?;
             if kind == TokenKind::With {
-                info.deps.insert(d);
+                info.add_dep(d, import);
             }
             let n = self.base.safe_next()?;
             match n.kind {
@@ -161,3 +174,38 @@ impl<'a> AdaScanner<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ada_lexer::{AdaLexer, AdaLexerOptions};
+    use crate::ada_scanner::AdaScanner;
+    use crate::files::File;
+    use crate::qnames::QName;
+    use crate::sourcefile::ImportKind;
+    use ustr::Ustr;
+
+    #[test]
+    fn test_with_kinds() {
+        let mut file = File::new_from_str(
+            "limited with A; private with B, C; limited private with D;
+             with C; with E; use E;
+             private package P.Q is end P.Q;",
+        );
+        let lex = AdaLexer::new(
+            &mut file,
+            AdaLexerOptions {
+                kw_aggregate: false,
+                kw_body: true,
+            },
+        )
+        .unwrap();
+        let info = AdaScanner::parse(lex).unwrap();
+        let kind = |n: &str| info.deps[&QName::new(vec![Ustr::from(n)])];
+        assert_eq!(kind("a"), ImportKind::Limited);
+        assert_eq!(kind("b"), ImportKind::Private);
+        assert_eq!(kind("c"), ImportKind::Regular);
+        assert_eq!(kind("d"), ImportKind::Limited);
+        assert_eq!(kind("e"), ImportKind::Regular);
+        assert_eq!(info.deps.len(), 5);
+    }
+}
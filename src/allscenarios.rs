@@ -7,10 +7,11 @@ use crate::{
     perscenario::PerScenario,
     rawexpr::WhenClause,
     scenario_variables::ScenarioVariable,
-    scenarios::{Scenario, ScenarioFactory, MAX_VALUES},
+    scenarios::{Scenario, ScenarioFactory},
     simplename::StringOrOthers,
 };
 use itertools::join;
+use serde::{Deserialize, Serialize};
 use ustr::{Ustr, UstrMap};
 
 /// Keeps the current state of a case statement.
@@ -28,9 +29,52 @@ pub struct CaseStmtScenario {
     // a WhenClause.
 }
 
+/// A scenario described by the values of its variables rather than by its
+/// bits, so that it can be stored in the cache and read back in a later run,
+/// where the variables might be allocated different bits.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NamedScenario {
+    // Whether the scenario applies to all values of the variables that are
+    // not listed.
+    all: bool,
+    vars: Vec<(String, Vec<String>)>,
+}
+
+/// Converts scenarios from a copy of an AllScenarios, in which more variables
+/// were declared, to the bits of the original one.
+pub struct ScenarioRemap<'a> {
+    // The variables allocated different bits in the copy and in the original
+    moved: Vec<(&'a ScenarioVariable, &'a ScenarioVariable)>,
+    // The bits of all variables in the copy
+    local_mask: Scenario,
+    // The bits of the moved variables in the copy
+    local_moved: Scenario,
+    // The bits of the moved variables in the original, and of the variables
+    // the copy doesn't know
+    target_mask: Scenario,
+    // The bits of the variables the copy doesn't know
+    unknown: Scenario,
+}
+
+impl ScenarioRemap<'_> {
+    pub fn remap(&self, scenario: &Scenario) -> Scenario {
+        let all = (scenario | &self.local_mask) == Scenario::default();
+        let mut result = scenario & !&self.local_moved & !&self.target_mask;
+        if all {
+            result = result | &self.unknown;
+        }
+        for (local, target) in &self.moved {
+            for value in local.values_in(scenario) {
+                result = result | target.mask(&value);
+            }
+        }
+        result
+    }
+}
+
 /// The collection of all variants of scenarios needed to analyze the project
 /// tree.  Each scenario is unique.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AllScenarios {
     variables: UstrMap<ScenarioVariable>,
     factory: ScenarioFactory,
@@ -39,12 +83,35 @@ pub struct AllScenarios {
 impl AllScenarios {
     /// True if this scenario is not applicable (cannot occur in practice).
     /// This is the case if for at least one of the variables the mask is 0.
-    pub fn never_matches(&self, scenario: Scenario) -> bool {
+    pub fn never_matches(&self, scenario: &Scenario) -> bool {
         self.variables
             .values()
             .any(|var| (scenario & var.full_mask()).is_empty())
     }
 
+    /// Compute the union of two scenarios, when it can be represented exactly
+    /// as a single scenario.  This is the case when one of them includes the
+    /// other, or when they only differ in the values of a single variable.
+    /// For instance "E1=a,E2=b" and "E1=c,E2=b" can be merged into
+    /// "E1=a|c,E2=b", but "E1=a,E2=b" and "E1=c,E2=d" cannot.
+    pub fn union(&self, s1: &Scenario, s2: &Scenario) -> Option<Scenario> {
+        let diff = (s1 & !s2) | (!s1 & s2);
+        if diff.is_empty()
+            || self
+                .variables
+                .values()
+                .any(|var| (&diff & !var.full_mask()).is_empty())
+        {
+            Some(s1 | s2)
+        } else if (s1 & s2) == *s1 {
+            Some(s2.clone())
+        } else if (s1 & s2) == *s2 {
+            Some(s1.clone())
+        } else {
+            None
+        }
+    }
+
     /// Negate the scenario: it returns a series of possibly overlapping
     /// scenarios so that they do not overlap scenario, yet the whole set
     /// covers the full space.
@@ -65,11 +132,11 @@ impl AllScenarios {
     ///    b 1111 01 11    # E1=*,   E2=f, E3=*
     pub fn negate(
         &self,
-        scenario: Scenario,
+        scenario: &Scenario,
     ) -> impl std::iter::Iterator<Item = Scenario> + '_ {
-        let mask = scenario;
+        let mask = !scenario;
         self.variables.values().filter_map(move |var| {
-            let negate_var_only = !mask & var.full_mask();
+            let negate_var_only = &mask & var.full_mask();
             let all_other_vars = !var.full_mask();
             if !negate_var_only.is_empty() {
                 Some(all_other_vars | negate_var_only)
@@ -101,7 +168,7 @@ impl AllScenarios {
         // Check that the value depends on exactly one scenario variable.
         let mut mask = Scenario::default();
         for (s, _) in variable_values.iter() {
-            mask = mask & *s;
+            mask = mask & s;
         }
 
         // Special case: the value is in fact independent of scenarios,
@@ -115,11 +182,11 @@ impl AllScenarios {
         }
 
         for v in self.variables.values() {
-            if (mask | v.full_mask()) == Scenario::default() {
+            if (&mask | v.full_mask()) == Scenario::default() {
                 return CaseStmtScenario {
                     var: *v.name(),
-                    full_mask: v.full_mask(),
-                    remaining: v.full_mask(),
+                    full_mask: v.full_mask().clone(),
+                    remaining: v.full_mask().clone(),
                 };
             }
         }
@@ -128,7 +195,7 @@ impl AllScenarios {
             "Variable used in case statement should have a simple \
              value that depends on exactly one scenario variable, \
              but got {:?}",
-            self.describe(*scenar_and_varname.0),
+            self.describe(scenar_and_varname.0),
         );
     }
 
@@ -137,7 +204,7 @@ impl AllScenarios {
     /// "when others" that doesn't match anything)
     pub fn process_when_clause(
         &mut self,
-        context: Scenario,
+        context: &Scenario,
         case_stmt: &mut CaseStmtScenario,
         when: &WhenClause,
     ) -> Scenario {
@@ -149,11 +216,11 @@ impl AllScenarios {
                 match val {
                     StringOrOthers::Str(value_in_when) => {
                         if *value_in_when == case_stmt.var {
-                            return context;
+                            return context.clone();
                         }
                     }
                     StringOrOthers::Others => {
-                        return context;
+                        return context.clone();
                     }
                 }
             }
@@ -166,12 +233,14 @@ impl AllScenarios {
                 match val {
                     StringOrOthers::Str(value_in_when) => {
                         let m = var.mask(value_in_when);
+                        case_stmt.remaining = &case_stmt.remaining & !&m;
                         mask = mask | m;
-                        case_stmt.remaining = case_stmt.remaining & !m;
                     }
                     StringOrOthers::Others => {
-                        mask = case_stmt.remaining;
-                        case_stmt.remaining = Scenario::empty();
+                        mask = std::mem::replace(
+                            &mut case_stmt.remaining,
+                            Scenario::empty(),
+                        );
                     }
                 }
             }
@@ -187,14 +256,14 @@ impl AllScenarios {
     ) -> Result<Scenario, Error> {
         let n = Ustr::from(name);
         match self.variables.get(&n) {
-            None => Err(Error::NotFound(format!("Unknown variable {}", name)))?,
+            None => Err(Error::NotFound(format!("Variable {}", name)))?,
             Some(var) => {
                 let mut result = Scenario::default() & !var.full_mask();
                 for v in values {
                     let m = var.mask(&Ustr::from(v));
                     if m == Scenario::empty() {
                         Err(Error::NotFound(format!(
-                            "Invalid value {} for variable {}",
+                            "Value {} for variable {}",
                             v, name
                         )))?;
                     }
@@ -205,6 +274,106 @@ impl AllScenarios {
         }
     }
 
+    /// Describe a scenario by the values of its variables
+    pub fn name_scenario(&self, scenario: &Scenario) -> NamedScenario {
+        let all_vars = self
+            .variables
+            .values()
+            .fold(Scenario::empty(), |mask, v| mask | v.full_mask());
+        let all = (scenario | &all_vars) == Scenario::default();
+        let mut vars: Vec<(String, Vec<String>)> = self
+            .variables
+            .values()
+            .filter_map(|v| {
+                let values = v.values_in(scenario);
+                let unchanged = if all {
+                    values.len() == v.count_valid()
+                } else {
+                    values.is_empty()
+                };
+                (!unchanged).then(|| {
+                    (
+                        v.name().to_string(),
+                        values.iter().map(|v| v.to_string()).collect(),
+                    )
+                })
+            })
+            .collect();
+        vars.sort();
+        NamedScenario { all, vars }
+    }
+
+    /// Build a scenario from the values of its variables.  The variables
+    /// must have been declared first.
+    pub fn lookup_named(
+        &self,
+        named: &NamedScenario,
+    ) -> Result<Scenario, Error> {
+        let mut result = if named.all {
+            Scenario::default()
+        } else {
+            Scenario::empty()
+        };
+        for (name, values) in &named.vars {
+            let var = self
+                .variables
+                .get(&Ustr::from(name))
+                .ok_or_else(|| Error::NotFound(format!("Variable {}", name)))?;
+            result = result & !var.full_mask();
+            for v in values {
+                let m = var.mask(&Ustr::from(v));
+                if m.is_empty() {
+                    Err(Error::NotFound(format!(
+                        "Value {} for variable {}",
+                        v, name
+                    )))?;
+                }
+                result = result | m;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Prepare the conversion of scenarios from `local`, a copy of self in
+    /// which more variables were declared.  These variables must have been
+    /// declared in self too.  Returns None when the bits are the same.
+    pub fn remap_from<'a>(
+        &'a self,
+        local: &'a AllScenarios,
+    ) -> Option<ScenarioRemap<'a>> {
+        let moved: Vec<_> = local
+            .variables
+            .iter()
+            .map(|(name, v)| (v, &self.variables[name]))
+            .filter(|(v, target)| v.full_mask() != target.full_mask())
+            .collect();
+        if moved.is_empty() {
+            return None;
+        }
+        let unknown = self
+            .variables
+            .iter()
+            .filter(|(name, _)| !local.variables.contains_key(*name))
+            .fold(Scenario::empty(), |mask, (_, v)| mask | v.full_mask());
+        let local_mask = local
+            .variables
+            .values()
+            .fold(Scenario::empty(), |mask, v| mask | v.full_mask());
+        let local_moved = moved
+            .iter()
+            .fold(Scenario::empty(), |mask, (v, _)| mask | v.full_mask());
+        let target_mask = moved
+            .iter()
+            .fold(unknown.clone(), |mask, (_, v)| mask | v.full_mask());
+        Some(ScenarioRemap {
+            moved,
+            local_mask,
+            local_moved,
+            target_mask,
+            unknown,
+        })
+    }
+
     /// Declares a new scenario variables and the list of all values it can
     /// accept.  If the variable is already declared, check that we are
     /// declaring the same set of values.
@@ -215,7 +384,6 @@ impl AllScenarios {
         valid: &[Ustr],
         default: Option<Ustr>,
     ) -> Result<&'a ScenarioVariable, Error> {
-        let mut error: Option<Error> = None;
         let res = self
             .variables
             .entry(name)
@@ -226,7 +394,7 @@ impl AllScenarios {
                          different types {:?} and {}",
                         name,
                         valid,
-                        v.describe(Scenario::default()),
+                        v.describe(&Scenario::default()),
                     );
                 }
                 if default.is_none() {
@@ -235,33 +403,17 @@ impl AllScenarios {
             })
             .or_insert_with(|| {
                 let mut full_mask = Scenario::empty();
-                let values: Result<Vec<(Ustr, Scenario)>, Error> = valid
+                let values: Vec<(Ustr, Scenario)> = valid
                     .iter()
                     .map(|v| {
-                        let s = self.factory.get_next()?;
-                        full_mask = full_mask | s;
-                        Ok((*v, s))
+                        let s = self.factory.get_next();
+                        full_mask = &full_mask | &s;
+                        (*v, s)
                     })
                     .collect();
-
-                match values {
-                    Ok(v) => ScenarioVariable::new(name, v, full_mask, default),
-                    Err(e) => {
-                        error = Some(e);
-                        ScenarioVariable::new(
-                            name,
-                            Vec::new(),
-                            full_mask,
-                            default,
-                        )
-                    }
-                }
+                ScenarioVariable::new(name, values, full_mask, default)
             });
-
-        match error {
-            None => Ok(res),
-            Some(e) => Err(e),
-        }
+        Ok(res)
     }
 
     /// Print statistics about scenario variables
@@ -269,10 +421,9 @@ impl AllScenarios {
         let total_valid: usize =
             self.variables.values().map(|v| v.count_valid()).sum();
         println!(
-            "Scenario variables:{:-7}, total values: {}, max values: {}",
+            "Scenario variables:{:-7}, total values: {}",
             self.variables.len(),
             total_valid,
-            MAX_VALUES
         );
 
         let mut vars: Vec<_> =
@@ -284,7 +435,7 @@ impl AllScenarios {
     }
 
     /// Debug output for variables
-    pub fn describe(&self, scenario: Scenario) -> String {
+    pub fn describe(&self, scenario: &Scenario) -> String {
         // Sort display, for tests
         let mut vars = self.variables.iter().collect::<Vec<_>>();
         vars.sort_by_key(|(name, _)| *name);
@@ -342,17 +493,17 @@ pub mod tests {
         try_add_variable(&mut scenarios, "MODE", &["debug", "lto", "optimize"]);
 
         let s0 = Scenario::default();
-        assert_eq!(scenarios.describe(s0), "*");
+        assert_eq!(scenarios.describe(&s0), "*");
 
         //  case Mode is
         //     when "debug" => ...
         let s2 = create_single(&mut scenarios, "MODE", &["debug"]);
-        assert_eq!(scenarios.describe(s2), "MODE=debug");
+        assert_eq!(scenarios.describe(&s2), "MODE=debug");
 
         //  when others  => for Source_Dirs use ("src1", "src3");
         //     case Check is
         let s3 = create_single(&mut scenarios, "MODE", &["lto", "optimize"]);
-        assert_eq!(scenarios.describe(s3), "MODE=lto|optimize");
+        assert_eq!(scenarios.describe(&s3), "MODE=lto|optimize");
 
         let same = create_single(&mut scenarios, "MODE", &["lto", "optimize"]);
         assert_eq!(same, s3);
@@ -361,22 +512,53 @@ pub mod tests {
         try_add_variable(&mut scenarios, "CHECK", &["most", "none", "some"]);
 
         let check_most = create_single(&mut scenarios, "CHECK", &["most"]);
-        let s4 = s3 & check_most;
-        assert_eq!(scenarios.describe(s4), "CHECK=most,MODE=lto|optimize");
+        let s4 = &s3 & &check_most;
+        assert_eq!(scenarios.describe(&s4), "CHECK=most,MODE=lto|optimize");
 
         let check_none_some =
             create_single(&mut scenarios, "CHECK", &["none", "some"]);
-        let s5 = s3 & check_none_some;
-        assert_eq!(scenarios.describe(s5), "CHECK=none|some,MODE=lto|optimize");
+        let s5 = &s3 & &check_none_some;
+        assert_eq!(
+            scenarios.describe(&s5),
+            "CHECK=none|some,MODE=lto|optimize"
+        );
 
         //   case Check is
         //      when "none" => for Excluded_Source_Files use ("a.ads");
         let check_none = create_single(&mut scenarios, "CHECK", &["none"]);
-        assert_eq!(scenarios.describe(check_none), "CHECK=none");
+        assert_eq!(scenarios.describe(&check_none), "CHECK=none");
 
         //      when others => null;
         let s7 = create_single(&mut scenarios, "CHECK", &["most", "none"]);
-        assert_eq!(scenarios.describe(s7), "CHECK=most|none");
+        assert_eq!(scenarios.describe(&s7), "CHECK=most|none");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_union() -> Result<(), Error> {
+        let mut scenarios = AllScenarios::default();
+        try_add_variable(&mut scenarios, "MODE", &["debug", "lto", "optimize"]);
+        try_add_variable(&mut scenarios, "CHECK", &["most", "none", "some"]);
+        let debug = create_single(&mut scenarios, "MODE", &["debug"]);
+        let lto = create_single(&mut scenarios, "MODE", &["lto"]);
+        let most = create_single(&mut scenarios, "CHECK", &["most"]);
+        let some = create_single(&mut scenarios, "CHECK", &["some"]);
+
+        // Only MODE differs
+        let res = scenarios.union(&(&debug & &most), &(&lto & &most));
+        assert_eq!(
+            scenarios.describe(&res.unwrap()),
+            "CHECK=most,MODE=debug|lto"
+        );
+
+        // One scenario includes the other
+        let res = scenarios.union(&debug, &(&debug & &some));
+        assert_eq!(res, Some(debug.clone()));
+
+        // Both variables differ
+        let res = scenarios.union(&(&debug & &most), &(&lto & &some));
+        assert_eq!(res, None);
 
         Ok(())
     }
@@ -392,19 +574,19 @@ pub mod tests {
         // s1=MODE=debug
         //    => s1
         let s1 = create_single(&mut scenarios, "MODE", &["debug"]);
-        let res = s0 & s1;
+        let res = &s0 & &s1;
         assert_eq!(res, s1);
-        let res = s1 & s0;
+        let res = &s1 & &s0;
         assert_eq!(res, s1);
 
         // s1=MODE=debug
         // s2=MODE=debug,CHECK=some
         //    => s2
         let check_some = create_single(&mut scenarios, "CHECK", &["some"]);
-        let s2 = s1 & check_some;
-        let res = s1 & s2;
+        let s2 = &s1 & &check_some;
+        let res = &s1 & &s2;
         assert_eq!(res, s2);
-        let res = s2 & s1; // reverse order
+        let res = &s2 & &s1; // reverse order
         assert_eq!(res, s2);
 
         // s2=MODE=debug,CHECK=some
@@ -412,9 +594,9 @@ pub mod tests {
         //    => s2=MODE=debug,CHECK=some
         let check_none_some =
             create_single(&mut scenarios, "CHECK", &["none", "some"]);
-        let res = s2 & check_none_some;
+        let res = &s2 & &check_none_some;
         assert_eq!(res, s2);
-        let res = check_none_some & s2; // reverse order
+        let res = &check_none_some & &s2; // reverse order
         assert_eq!(res, s2);
 
         // s4=MODE=debug|optimize,CHECK=some
@@ -422,19 +604,148 @@ pub mod tests {
         //    =>  s6=MODE=optimize,CHECK=some
         let mode_debug_opt =
             create_single(&mut scenarios, "MODE", &["debug", "optimize"]);
-        let s4 = mode_debug_opt & check_some;
+        let s4 = &mode_debug_opt & &check_some;
         let mode_lto_opt =
             create_single(&mut scenarios, "MODE", &["lto", "optimize"]);
         let check_some_most =
             create_single(&mut scenarios, "CHECK", &["some", "most"]);
-        let s5 = mode_lto_opt & check_some_most;
+        let s5 = &mode_lto_opt & &check_some_most;
         let mode_opt = create_single(&mut scenarios, "MODE", &["optimize"]);
-        let s6 = mode_opt & check_some;
-        let res = s4 & s5;
+        let s6 = &mode_opt & &check_some;
+        let res = &s4 & &s5;
         assert_eq!(res, s6);
-        let res = s5 & s4; // reverse order
+        let res = &s5 & &s4; // reverse order
         assert_eq!(res, s6);
 
         Ok(())
     }
+
+    #[test]
+    fn wide_scenarios() -> Result<(), Error> {
+        // The values of WIDE span the first two words of the bitmask
+        let mut scenarios = AllScenarios::default();
+        let filler: Vec<String> =
+            (0..60).map(|i| format!("v{:02}", i)).collect();
+        let filler: Vec<&str> = filler.iter().map(String::as_str).collect();
+        try_add_variable(&mut scenarios, "FILLER", &filler);
+        try_add_variable(
+            &mut scenarios,
+            "WIDE",
+            &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"],
+        );
+        let low = create_single(&mut scenarios, "WIDE", &["a", "b"]);
+        let high = create_single(&mut scenarios, "WIDE", &["i", "j"]);
+        let both = create_single(&mut scenarios, "WIDE", &["a", "j"]);
+        let last = create_single(&mut scenarios, "FILLER", &["v59"]);
+
+        assert_eq!(scenarios.describe(&(&low | &high)), "WIDE=a|b|i|j");
+        assert_eq!(scenarios.describe(&(&both & &high)), "WIDE=j");
+        assert_eq!(scenarios.describe(&(&both & &low)), "WIDE=a");
+        assert_eq!(scenarios.describe(&(&last & &high)), "FILLER=v59,WIDE=i|j");
+        assert_eq!(!!both.clone(), both);
+
+        assert!(scenarios.never_matches(&(&low & &high)));
+        assert!(scenarios.never_matches(&(&both & !&both)));
+        assert!(!scenarios.never_matches(&(&last & &both)));
+
+        let negated: Vec<String> = scenarios
+            .negate(&both)
+            .map(|s| scenarios.describe(&s))
+            .collect();
+        assert_eq!(negated, vec!["WIDE=b|c|d|e|f|g|h|i"]);
+        let negated: Vec<Scenario> = scenarios.negate(&high).collect();
+        assert_eq!(
+            negated,
+            vec![create_single(
+                &mut scenarios,
+                "WIDE",
+                &["a", "b", "c", "d", "e", "f", "g", "h"],
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn named_scenarios() -> Result<(), Error> {
+        let mut scenarios = AllScenarios::default();
+        try_add_variable(&mut scenarios, "MODE", &["debug", "optimize"]);
+        try_add_variable(&mut scenarios, "CHECK", &["most", "none", "some"]);
+        let mode = create_single(&mut scenarios, "MODE", &["debug"]);
+        let check = create_single(&mut scenarios, "CHECK", &["none", "some"]);
+
+        // The same variables, allocated in a different order
+        let mut other = AllScenarios::default();
+        try_add_variable(&mut other, "CHECK", &["most", "none", "some"]);
+        try_add_variable(&mut other, "MODE", &["debug", "optimize"]);
+
+        let all = [
+            Scenario::default(),
+            Scenario::empty(),
+            mode.clone(),
+            &mode & &check,
+            &mode | &check,
+            &mode & !&mode,
+        ];
+        for s in &all {
+            let named = scenarios.name_scenario(s);
+            let remapped = other.lookup_named(&named)?;
+            assert_eq!(other.describe(&remapped), scenarios.describe(s));
+            assert_eq!(other.name_scenario(&remapped), named);
+        }
+
+        // Variables unknown to the original scenario do not restrict it
+        try_add_variable(&mut other, "EXTRA", &["a", "b"]);
+        for s in &all {
+            let named = scenarios.name_scenario(s);
+            let remapped = other.lookup_named(&named)?;
+            assert_eq!(other.name_scenario(&remapped), named);
+        }
+
+        let extra = create_single(&mut other, "EXTRA", &["a"]);
+        let named = other.name_scenario(&extra);
+        assert_eq!(
+            scenarios.lookup_named(&named).unwrap_err().to_string(),
+            "Variable EXTRA not found",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn remap_scenarios() -> Result<(), Error> {
+        let mut global = AllScenarios::default();
+        try_add_variable(&mut global, "MODE", &["debug", "optimize"]);
+
+        // Nothing to convert while the copy declares no new variable
+        let mut local = global.clone();
+        try_add_variable(&mut local, "MODE", &["debug", "optimize"]);
+        assert!(global.remap_from(&local).is_none());
+
+        // Another copy declared OTHER first, so X gets different bits
+        try_add_variable(&mut local, "X", &["a", "b", "c"]);
+        try_add_variable(&mut global, "OTHER", &["p", "q"]);
+        try_add_variable(&mut global, "X", &["a", "b", "c"]);
+        let mode = create_single(&mut local, "MODE", &["debug"]);
+        let x = create_single(&mut local, "X", &["a", "c"]);
+        let remap = global.remap_from(&local).unwrap();
+        for s in [
+            Scenario::default(),
+            Scenario::empty(),
+            mode.clone(),
+            x.clone(),
+            &mode & &x,
+            &mode | &x,
+            &x & !&x,
+        ] {
+            let remapped = remap.remap(&s);
+            assert_eq!(
+                global.name_scenario(&remapped),
+                local.name_scenario(&s)
+            );
+        }
+        assert_eq!(
+            global.describe(&remap.remap(&(&mode & &x))),
+            "MODE=debug,X=a|c"
+        );
+        Ok(())
+    }
 }
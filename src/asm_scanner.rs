@@ -0,0 +1,70 @@
+use crate::{
+    errors::Error,
+    files::File,
+    qnames::QName,
+    scanners::{parse_hash_include, LanguageScanner},
+    sourcefile::{Include, ParseResult, SourceKind},
+};
+use std::path::Path;
+use ustr::Ustr;
+
+/// Scanner for GNU assembler files.  Their dependencies are the files given
+/// in `.include` directives, or in `#include` when the file goes through the
+/// C preprocessor first (Asm_Cpp).  Each file is its own unit.
+pub struct AsmScanner;
+
+impl LanguageScanner for AsmScanner {
+    fn parse(
+        &self,
+        file: &mut File,
+        path: &Path,
+    ) -> Result<ParseResult, Error> {
+        let mut info = ParseResult {
+            unitname: QName::new(vec![Ustr::from(&path.to_string_lossy())]),
+            kind: SourceKind::Implementation,
+            deps: Default::default(),
+            includes: Default::default(),
+        };
+        for line in file.as_mut_str().lines() {
+            if let Some(inc) = parse_hash_include(line) {
+                info.includes.push(inc);
+            } else if let Some(rest) =
+                line.trim_start().strip_prefix(".include")
+            {
+                if let Some(path) = rest.trim_start().strip_prefix('"') {
+                    if let Some(path) = path.split('"').next() {
+                        info.includes.push(Include {
+                            path: Ustr::from(path),
+                            system: false,
+                            condition: None,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm_scanner::AsmScanner;
+    use crate::files::File;
+    use crate::scanners::LanguageScanner;
+    use std::path::Path;
+
+    #[test]
+    fn test_includes() {
+        let mut file = File::new_from_str(
+            "# comment\n   .include \"macros.s\"\n#include <sys/asm.h>\n\
+             mov %eax, %ebx\n",
+        );
+        let info = AsmScanner.parse(&mut file, Path::new("a.S")).unwrap();
+        let found: Vec<(&str, bool)> = info
+            .includes
+            .iter()
+            .map(|i| (i.path.as_str(), i.system))
+            .collect();
+        assert_eq!(found, vec![("macros.s", false), ("sys/asm.h", true)]);
+    }
+}
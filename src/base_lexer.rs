@@ -66,6 +66,11 @@ impl<'a> BaseLexer<'a> {
         self.context = ctx;
     }
 
+    /// The current character
+    pub fn current(&self) -> char {
+        self.context.current
+    }
+
     /// Whether the current character is valid for an identifier
     pub fn is_wordchar(&self) -> bool {
         matches!(
@@ -213,6 +218,11 @@ impl<LEXER: Lexer> BaseScanner<LEXER> {
         self.peeked.kind.clone()
     }
 
+    /// The line of the next token, without consuming it
+    pub fn peek_line(&self) -> u32 {
+        self.peeked.line
+    }
+
     /// Consume the next token in the stream
     pub fn next_token(&mut self) -> Option<Token> {
         let ctx = self.lex.save_context();
@@ -0,0 +1,470 @@
+//! An on-disk cache of the parsed source files and evaluated projects.
+//! Scanning every Ada and C file is by far the most expensive part of loading
+//! a large tree, so we keep the result of `SourceFile::parse` for each file,
+//! and only parse again the files that were modified since the previous run.
+//!
+//! Project files are still parsed on every run, to discover the projects
+//! they import, but the values of their variables and attributes are reused.
+//! Scenario variables are allocated bits while processing the projects, so
+//! those values are stored with named scenarios, and converted back to the
+//! bits of the current run when loaded.
+//! A project is evaluated again when it changed on the disk, when one of the
+//! projects it imports (directly or not) was evaluated again, or when one of
+//! the environment variables it reads changed.  When a project or one of its
+//! imported projects changes, the naming scheme or languages of its sources
+//! might have changed too, so all the sources of the project are parsed
+//! again.
+
+use crate::{
+    allscenarios::{AllScenarios, NamedScenario},
+    errors::Error,
+    packagename::PackageName,
+    qnames::QName,
+    simplename::SimpleName,
+    sourcefile::{ImportKind, Include, ParseResult, SourceKind},
+    values::ExprValue,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use ustr::Ustr;
+
+// Increment whenever the format of the cache, or the output of the scanners,
+// changes.  Caches with a different version are simply discarded.
+const CACHE_VERSION: u32 = 1;
+
+/// Serialize a `Ustr` as a string, with
+/// `#[serde(with = "crate::cache::ustr_serde")]`
+pub mod ustr_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use ustr::Ustr;
+
+    pub fn serialize<S: Serializer>(
+        value: &Ustr,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Ustr, D::Error> {
+        String::deserialize(deserializer).map(|s| Ustr::from(&s))
+    }
+}
+
+/// Identifies one version of a file on the disk
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize,
+)]
+pub struct Stamp {
+    mtime: u128, // nanoseconds since the epoch
+    size: u64,
+}
+
+impl Stamp {
+    /// The stamp for a file, or None if it cannot be accessed
+    pub fn new(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Stamp {
+            mtime: meta
+                .modified()
+                .ok()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?
+                .as_nanos(),
+            size: meta.len(),
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedSource {
+    stamp: Stamp,
+    lang: String,
+    unitname: Vec<String>,
+    kind: SourceKind,
+    deps: Vec<(Vec<String>, ImportKind)>,
+    includes: Vec<CachedInclude>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedInclude {
+    path: String,
+    system: bool,
+    condition: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedProject {
+    stamp: Stamp,
+    imports: Vec<PathBuf>, // imported and extended projects
+    sources: Vec<PathBuf>,
+}
+
+/// The value of a variable or attribute, for each scenario
+#[derive(Deserialize, Serialize)]
+pub enum CachedValue {
+    Str(Vec<(NamedScenario, String)>),
+    StrList(Vec<(NamedScenario, Vec<String>)>),
+}
+
+impl CachedValue {
+    pub fn new(value: &ExprValue, scenarios: &AllScenarios) -> Self {
+        match value {
+            ExprValue::Str(v) => CachedValue::Str(
+                v.iter()
+                    .map(|(s, v)| (scenarios.name_scenario(s), v.to_string()))
+                    .collect(),
+            ),
+            ExprValue::StrList(v) => CachedValue::StrList(
+                v.iter()
+                    .map(|(s, v)| {
+                        (scenarios.name_scenario(s), ustrs_to_strings(v))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The value, using the scenarios of the current run
+    pub fn value(&self, scenarios: &AllScenarios) -> Result<ExprValue, Error> {
+        Ok(match self {
+            CachedValue::Str(v) => ExprValue::Str(
+                v.iter()
+                    .map(|(s, v)| {
+                        Ok((scenarios.lookup_named(s)?, Ustr::from(v)))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+            CachedValue::StrList(v) => ExprValue::StrList(
+                v.iter()
+                    .map(|(s, v)| {
+                        Ok((scenarios.lookup_named(s)?, strings_to_ustrs(v)))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+        })
+    }
+}
+
+/// The result of evaluating a project file
+#[derive(Deserialize, Serialize)]
+pub struct CachedGpr {
+    // The scenario variables declared in the project: name, valid values
+    // and default, in the order of their declaration.
+    pub variables: Vec<(String, Vec<String>, Option<String>)>,
+    pub types: Vec<(PackageName, SimpleName, Vec<String>)>,
+    pub values: Vec<(PackageName, SimpleName, CachedValue)>,
+
+    // Other projects referenced by name
+    pub referenced: Vec<PathBuf>,
+
+    // Environment variables read by the project, and their value
+    pub externals: Vec<(String, Option<String>)>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Cache {
+    version: u32,
+    sources: HashMap<PathBuf, CachedSource>,
+    projects: HashMap<PathBuf, CachedProject>,
+    gprs: HashMap<PathBuf, CachedGpr>,
+
+    // Number of source files parsed, or found in the cache, in this run
+    #[serde(skip)]
+    pub parsed: usize,
+    #[serde(skip)]
+    pub reused: usize,
+
+    // Number of projects evaluated, or found in the cache, in this run
+    #[serde(skip)]
+    pub gprs_evaluated: usize,
+    #[serde(skip)]
+    pub gprs_reused: usize,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            version: CACHE_VERSION,
+            sources: HashMap::new(),
+            projects: HashMap::new(),
+            gprs: HashMap::new(),
+            parsed: 0,
+            reused: 0,
+            gprs_evaluated: 0,
+            gprs_reused: 0,
+        }
+    }
+}
+
+fn qname_to_strings(name: &QName) -> Vec<String> {
+    name.0.iter().map(|u| u.to_string()).collect()
+}
+
+fn strings_to_qname(name: &[String]) -> QName {
+    QName::new(strings_to_ustrs(name))
+}
+
+pub fn ustrs_to_strings(list: &[Ustr]) -> Vec<String> {
+    list.iter().map(|u| u.to_string()).collect()
+}
+
+pub fn strings_to_ustrs(list: &[String]) -> Vec<Ustr> {
+    list.iter().map(|s| Ustr::from(s)).collect()
+}
+
+impl Cache {
+    /// Load the cache from the disk.  A missing, unreadable or outdated
+    /// cache is not an error, we just start with an empty one.
+    pub fn load(path: &Path) -> Self {
+        let mut cache = match std::fs::read_to_string(path) {
+            Err(_) => Cache::default(),
+            Ok(content) => match serde_json::from_str::<Cache>(&content) {
+                Ok(c) if c.version == CACHE_VERSION => c,
+                Ok(_) => {
+                    debug!("Discarding outdated cache {}", path.display());
+                    Cache::default()
+                }
+                Err(e) => {
+                    debug!("Discarding cache {}: {}", path.display(), e);
+                    Cache::default()
+                }
+            },
+        };
+        cache.invalidate_projects();
+        cache
+    }
+
+    /// Save the cache to the disk
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = serde_json::to_string(self)
+            .map_err(|e| Error::CannotWriteCache(path.into(), e.to_string()))?;
+        std::fs::write(path, content)
+            .map_err(|e| Error::CannotWriteCache(path.into(), e.to_string()))
+    }
+
+    /// Remove the evaluation and the sources of all projects that changed on
+    /// the disk, or that import (directly or not) such a project.
+    fn invalidate_projects(&mut self) {
+        let mut stale: HashSet<PathBuf> = self
+            .projects
+            .iter()
+            .filter(|(path, prj)| Stamp::new(path) != Some(prj.stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        loop {
+            let importers: Vec<PathBuf> = self
+                .projects
+                .iter()
+                .filter(|(path, prj)| {
+                    !stale.contains(*path)
+                        && prj.imports.iter().any(|i| {
+                            stale.contains(i) || !self.projects.contains_key(i)
+                        })
+                })
+                .map(|(path, _)| path.clone())
+                .collect();
+            if importers.is_empty() {
+                break;
+            }
+            stale.extend(importers);
+        }
+
+        for path in stale {
+            debug!("Project changed: {}", path.display());
+            if let Some(prj) = self.projects.remove(&path) {
+                for s in prj.sources {
+                    self.sources.remove(&s);
+                }
+            }
+        }
+        self.gprs.retain(|path, _| self.projects.contains_key(path));
+    }
+
+    /// Return the parse result for a file, if it hasn't changed since it was
+    /// cached.
+    pub fn get_source(
+        &mut self,
+        path: &Path,
+        lang: Ustr,
+    ) -> Option<ParseResult> {
+        let cached = self.sources.get(path)?;
+        if cached.lang != lang.as_str()
+            || Stamp::new(path) != Some(cached.stamp)
+        {
+            return None;
+        }
+        self.reused += 1;
+        Some(ParseResult {
+            unitname: strings_to_qname(&cached.unitname),
+            kind: cached.kind,
+            deps: cached
+                .deps
+                .iter()
+                .map(|(d, k)| (strings_to_qname(d), *k))
+                .collect(),
+            includes: cached
+                .includes
+                .iter()
+                .map(|i| Include {
+                    path: Ustr::from(&i.path),
+                    system: i.system,
+                    condition: i.condition.as_deref().map(Ustr::from),
+                })
+                .collect(),
+        })
+    }
+
+    /// Store the parse result for a file
+    pub fn set_source(&mut self, path: &Path, lang: Ustr, info: &ParseResult) {
+        self.parsed += 1;
+        if let Some(stamp) = Stamp::new(path) {
+            let mut deps: Vec<(Vec<String>, ImportKind)> = info
+                .deps
+                .iter()
+                .map(|(d, k)| (qname_to_strings(d), *k))
+                .collect();
+            deps.sort();
+            self.sources.insert(
+                path.into(),
+                CachedSource {
+                    stamp,
+                    lang: lang.to_string(),
+                    unitname: qname_to_strings(&info.unitname),
+                    kind: info.kind,
+                    deps,
+                    includes: info
+                        .includes
+                        .iter()
+                        .map(|i| CachedInclude {
+                            path: i.path.to_string(),
+                            system: i.system,
+                            condition: i.condition.map(|c| c.to_string()),
+                        })
+                        .collect(),
+                },
+            );
+        }
+    }
+
+    /// Forget about the source files that are no longer part of any project
+    pub fn retain_sources<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Path) -> bool,
+    {
+        self.sources.retain(|path, _| keep(path));
+    }
+
+    /// Forget about the projects that are no longer part of the tree
+    pub fn retain_projects<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Path) -> bool,
+    {
+        self.projects.retain(|path, _| keep(path));
+        self.gprs.retain(|path, _| keep(path));
+    }
+
+    /// Return the evaluation of a project in a previous run, if the project
+    /// still imports the same projects (sorted), and the environment
+    /// variables it reads have not changed.  The caller must also check
+    /// that none of the imported projects was evaluated again.
+    pub fn get_gpr(
+        &mut self,
+        path: &Path,
+        imports: &[PathBuf],
+    ) -> Option<&CachedGpr> {
+        let prj = self.projects.get(path)?;
+        let gpr = self.gprs.get(path)?;
+        if prj.imports != imports
+            || gpr
+                .externals
+                .iter()
+                .any(|(name, value)| std::env::var(name).ok() != *value)
+        {
+            return None;
+        }
+        self.gprs_reused += 1;
+        Some(gpr)
+    }
+
+    /// Store the evaluation of a project
+    pub fn set_gpr(&mut self, path: &Path, gpr: CachedGpr) {
+        self.gprs_evaluated += 1;
+        self.gprs.insert(path.into(), gpr);
+    }
+
+    /// Record the current state of a project
+    pub fn set_project(
+        &mut self,
+        path: &Path,
+        imports: Vec<PathBuf>,
+        sources: Vec<PathBuf>,
+    ) {
+        match Stamp::new(path) {
+            None => {
+                self.projects.remove(path);
+            }
+            Some(stamp) => {
+                self.projects.insert(
+                    path.into(),
+                    CachedProject {
+                        stamp,
+                        imports,
+                        sources,
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, Stamp};
+    use crate::{
+        environment::tests::TempDir,
+        qnames::QName,
+        sourcefile::{ParseResult, SourceKind},
+    };
+    use ustr::Ustr;
+
+    #[test]
+    fn test_invalidation() {
+        let tmp = TempDir::new("cache").unwrap();
+        let dir = &tmp.0;
+        let src = dir.join("a.ads");
+        let gpr = dir.join("a.gpr");
+        let dep = dir.join("b.gpr");
+        let cachefile = dir.join("cache.json");
+        std::fs::write(&src, "package A is end A;").unwrap();
+        std::fs::write(&gpr, "with \"b\"; project A is end A;").unwrap();
+        std::fs::write(&dep, "project B is end B;").unwrap();
+        let ada = Ustr::from("ada");
+
+        let mut cache = Cache::default();
+        let info = ParseResult {
+            unitname: QName::from_str(&Ustr::from("a"), "."),
+            kind: SourceKind::Spec,
+            deps: Default::default(),
+            includes: Default::default(),
+        };
+        cache.set_source(&src, ada, &info);
+        cache.set_project(&gpr, vec![dep.clone()], vec![src.clone()]);
+        cache.set_project(&dep, vec![], vec![]);
+        cache.save(&cachefile).unwrap();
+
+        let mut cache = Cache::load(&cachefile);
+        assert!(cache.get_source(&src, ada).is_some());
+        assert!(cache.get_source(&src, Ustr::from("c")).is_none());
+
+        // Modifying an imported project invalidates the sources
+        std::fs::write(&dep, "project B is end B;  ").unwrap();
+        assert_ne!(Stamp::new(&dep), None);
+        let mut cache = Cache::load(&cachefile);
+        assert!(cache.get_source(&src, ada).is_none());
+    }
+}
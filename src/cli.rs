@@ -1,15 +1,30 @@
 use crate::{
-    action_check::ActionCheck, action_imported::ActionImported,
-    action_path::ActionPath, action_stats::ActionStats, errors::Error,
+    action_affected::ActionAffected,
+    action_build_plan::ActionBuildPlan,
+    action_check::ActionCheck,
+    action_closure::ActionClosure,
+    action_export::{ActionExport, ExportFormat},
+    action_imported::ActionImported,
+    action_path::ActionPath,
+    action_redundant_withs::ActionRedundantWiths,
+    action_stats::ActionStats,
+    action_unused_withs::ActionUnusedWiths,
+    errors::Error,
     settings::Settings,
 };
 use clap::{arg, ArgAction, ArgMatches, Command};
 use std::path::{Path, PathBuf};
 
 pub enum Action {
+    Affected(ActionAffected),
+    BuildPlan(ActionBuildPlan),
     Check(ActionCheck),
+    Closure(ActionClosure),
     Dependencies(ActionImported),
+    GraphExport(ActionExport),
     GprShow { gprpath: PathBuf, print_vars: bool },
+    GprRedundantWiths(ActionRedundantWiths),
+    GprUnusedWiths(ActionUnusedWiths),
     ImportPath(ActionPath),
     Stats(ActionStats),
 }
@@ -91,6 +106,17 @@ fn get_path_and_root(
         .collect()
 }
 
+/// Split the value of an environment variable into a list of directories,
+/// using the platform's path separator.
+fn env_path_list(var: &str) -> Vec<PathBuf> {
+    match std::env::var_os(var) {
+        None => vec![],
+        Some(val) => std::env::split_paths(&val)
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect(),
+    }
+}
+
 pub fn parse_cli() -> Result<(Settings, Action), Error> {
     let matches = Command::new("gprdeps")
         .version("1.0")
@@ -100,6 +126,9 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
         .flatten_help(true) // Show help for all subcommands as well
         .arg_required_else_help(true) // show full help if nothing given
         .args([
+            arg!(--cache <FILE> "Keep parsed source files in FILE, and only parse modified files in later runs")
+                .global(true)
+                .value_parser(clap::value_parser!(PathBuf)),
             arg!(--missing_sources "Report missing sources")
                 .global(true)
                 .action(ArgAction::SetTrue),
@@ -109,6 +138,9 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
             arg!(--runtime [RUNTIME]... "Projects implicitly imported by all (relative to root dirs or current dir)")
                 .global(true)
                 .value_parser(clap::value_parser!(PathBuf)),
+            arg!(--"project-path" <DIR>... "Directories to search for imported projects, before GPR_PROJECT_PATH and ADA_PROJECT_PATH")
+                .global(true)
+                .value_parser(clap::value_parser!(PathBuf)),
             arg!(--root <DIR_OR_GPR>... "Root directory or project")
                 .global(true)
                 .default_value(".")
@@ -143,6 +175,14 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                                 .value_parser(clap::value_parser!(PathBuf)),
                         ]),
                 )
+                .subcommand(
+                    Command::new("closure")
+                        .about("Show all files needed to build MAIN")
+                        .args([
+                            arg!(<MAIN> "Path to the main source file (relative to root dirs or current dir)")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ]),
+                )
                 .subcommand(
                     Command::new("import")
                         .about("Show all files importedby PATH")
@@ -166,6 +206,25 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                         .value_parser(clap::value_parser!(PathBuf)),
                 ]),
         )
+        .subcommand(
+            Command::new("affected")
+                .about("Show files and projects impacted by modified files. \
+                        Modified files are read from stdin if PATH is -, or \
+                        if neither PATH nor --since is given")
+                .args([
+                    arg!([PATH]... "Modified files (relative to root dirs or current dir), or - to read them from stdin")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--since <REV> "Also use the files modified since this git revision"),
+                ]),
+        )
+        .subcommand(
+            Command::new("build-plan")
+                .about("Show the order in which to compile the sources of PROJECT")
+                .args([
+                    arg!(<PROJECT> "Project to analyze (relative to root dirs or current dir)")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ]),
+        )
         .subcommand(
             Command::new("check")
                 .about("Show unused source files, duplicate basenames,...")
@@ -184,6 +243,28 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                         .action(ArgAction::SetTrue),
                 ]),
         )
+        .subcommand(
+            Command::new("graph")
+                .about("Subcommands for the whole dependency graph")
+                .flatten_help(true)
+                .disable_help_subcommand(true)
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Output the graph for external tools")
+                        .args([
+                            arg!(--format <FORMAT> "Output format")
+                                .value_parser(["dot", "graphml", "json", "mermaid"])
+                                .default_value("dot"),
+                            arg!(--projects "Keep project nodes (default: all kinds)")
+                                .action(ArgAction::SetTrue),
+                            arg!(--units "Keep unit nodes (default: all kinds)")
+                                .action(ArgAction::SetTrue),
+                            arg!(--sources "Keep source file nodes (default: all kinds)")
+                                .action(ArgAction::SetTrue),
+                        ]),
+                ),
+        )
         .subcommand(
             Command::new("gpr")
                 .about("Subcommands at the project level")
@@ -199,6 +280,15 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                             arg!(--print_vars  "Display values of variables")
                                 .action(ArgAction::SetTrue),
                         ]),
+                )
+                .subcommand(
+                    Command::new("redundant-withs").about(
+                        "Show project imports already implied by other imports",
+                    ),
+                )
+                .subcommand(
+                    Command::new("unused-withs")
+                        .about("Show project imports that no source needs"),
                 ),
         )
         .get_matches();
@@ -207,6 +297,11 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
         report_missing_source_dirs: matches.get_flag("missing_sources"),
         resolve_symbolic_links: matches.get_flag("symlinks"),
         runtime_gpr: vec![],
+        project_path: get_path_list(&matches, "project-path", None)
+            .into_iter()
+            .chain(env_path_list("GPR_PROJECT_PATH"))
+            .chain(env_path_list("ADA_PROJECT_PATH"))
+            .collect(),
         root: get_path_list(&matches, "root", None),
         trim: matches.get_flag("trim"),
         relto: get_path(&matches, "relto", None)?,
@@ -222,6 +317,7 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                 ),
             })
             .collect(),
+        cache: matches.get_one::<PathBuf>("cache").cloned(),
     };
     settings.runtime_gpr = get_path_list(&matches, "runtime", Some(&settings));
 
@@ -235,6 +331,9 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
                     kind: crate::action_imported::Kind::ImportedBy,
                 })
             }
+            Some(("closure", closuresub)) => Action::Closure(ActionClosure {
+                path: get_path(closuresub, "MAIN", Some(&settings))?,
+            }),
             Some(("import", importsub)) => {
                 Action::Dependencies(ActionImported {
                     path: get_path(importsub, "PATH", Some(&settings))?,
@@ -249,17 +348,68 @@ pub fn parse_cli() -> Result<(Settings, Action), Error> {
             target: get_path(importsub, "file2", Some(&settings))?,
             show_units: false,
         }),
+        Some(("affected", sub)) => {
+            // "-" reads the list of files from stdin, in addition to the
+            // other paths
+            let dash = sub
+                .get_many::<PathBuf>("PATH")
+                .into_iter()
+                .flatten()
+                .any(|p| p.as_os_str() == "-");
+            Action::Affected(ActionAffected {
+                paths: get_path_list(sub, "PATH", Some(&settings))
+                    .into_iter()
+                    .filter(|p| p.file_name().is_some_and(|f| f != "-"))
+                    .collect(),
+                since: sub.get_one::<String>("since").cloned(),
+                stdin: dash
+                    || (!sub.contains_id("PATH") && !sub.contains_id("since")),
+            })
+        }
+        Some(("build-plan", sub)) => Action::BuildPlan(ActionBuildPlan {
+            gprpath: get_path(sub, "PROJECT", Some(&settings))?,
+        }),
         Some(("check", importsub)) => Action::Check(ActionCheck::new(
             get_path_and_root(importsub, "unused", Some(&settings)),
             get_path_list(importsub, "ignore", Some(&settings)),
             !importsub.get_flag("no_recurse"),
             importsub.get_flag("quiet"),
         )),
+        Some(("graph", sub)) => match sub.subcommand() {
+            Some(("export", exportsub)) => {
+                let projects = exportsub.get_flag("projects");
+                let units = exportsub.get_flag("units");
+                let sources = exportsub.get_flag("sources");
+                let all = !projects && !units && !sources;
+                Action::GraphExport(ActionExport {
+                    format: match exportsub
+                        .get_one::<String>("format")
+                        .unwrap()
+                        .as_str()
+                    {
+                        "graphml" => ExportFormat::GraphML,
+                        "json" => ExportFormat::Json,
+                        "mermaid" => ExportFormat::Mermaid,
+                        _ => ExportFormat::Dot,
+                    },
+                    projects: projects || all,
+                    units: units || all,
+                    sources: sources || all,
+                })
+            }
+            _ => unreachable!(),
+        },
         Some(("gpr", sub)) => match sub.subcommand() {
             Some(("show", showsub)) => Action::GprShow {
                 gprpath: get_path(showsub, "PROJECT", Some(&settings))?,
                 print_vars: showsub.get_flag("print_vars"),
             },
+            Some(("redundant-withs", _)) => {
+                Action::GprRedundantWiths(ActionRedundantWiths::new())
+            }
+            Some(("unused-withs", _)) => {
+                Action::GprUnusedWiths(ActionUnusedWiths::new())
+            }
             _ => unreachable!(),
         },
         _ => unreachable!(),
@@ -44,12 +44,14 @@ impl<'a> CppLexer<'a> {
                 }
                 ('#', false) => {
                     // Skip all preprocessor directives, except for #include
-                    // which we need for dependencies
+                    // which we need for dependencies, and the conditionals
+                    // which might apply to them.
                     let ctx = self.base.save_context();
                     self.base.scan_char(); //  consume '#'
                     self.base.skip_whitespaces();
                     match &*self.base.scan_identifier() {
-                        "include" => {
+                        "include" | "if" | "ifdef" | "ifndef" | "elif"
+                        | "else" | "endif" => {
                             self.base.restore_context(ctx);
                             break;
                         }
@@ -79,22 +81,120 @@ impl<'a> CppLexer<'a> {
         }
     }
 
-    fn scan_include(&mut self) -> TokenKind {
+    /// Return the rest of the current line, taking continuation lines into
+    /// account, and with comments removed and whitespaces collapsed.
+    fn scan_directive_text(&mut self) -> String {
+        let mut text = String::new();
+        loop {
+            match (self.base.current(), self.base.peek_char()) {
+                ('\x00' | '\n', _) => break,
+                ('\\', Some('\n')) => {
+                    self.base.scan_char(); // consume backslash
+                    text.push(' ');
+                }
+                ('/', Some('/')) => {
+                    self.base.skip_to_eol();
+                    break;
+                }
+                ('/', Some('*')) => {
+                    self.base.scan_char(); // consume '/'
+                    loop {
+                        match (self.base.scan_char(), self.base.peek_char()) {
+                            ('\x00', _) => return text,
+                            ('*', Some('/')) => break,
+                            _ => {}
+                        }
+                    }
+                    self.base.scan_char(); // consume '*'
+                    text.push(' ');
+                }
+                (c, _) => text.push(c),
+            }
+            self.base.scan_char();
+        }
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Scan a preprocessor directive, either #include or a conditional.
+    /// Conditions of #ifdef and #ifndef are converted to a "defined(...)"
+    /// expression.
+    fn scan_directive(&mut self) -> TokenKind {
         self.base.scan_char(); // consume '#'
         self.base.skip_whitespaces();
-        let directive = &*self.base.scan_identifier();
-        assert_eq!(directive, "include");
-        self.base.skip_whitespaces();
+        let directive = self.base.scan_identifier().to_string();
+        while let ' ' | '\t' = self.base.current() {
+            self.base.scan_char(); // not newlines, the directive might be empty
+        }
+        match directive.as_str() {
+            "include" => self.scan_include(),
+            "if" => TokenKind::HashIf(Ustr::from(&self.scan_directive_text())),
+            "ifdef" | "ifndef" => {
+                let text = self.scan_directive_text();
+                let name = text.split_whitespace().next().unwrap_or_default();
+                TokenKind::HashIf(Ustr::from(&if directive == "ifdef" {
+                    format!("defined({})", name)
+                } else {
+                    format!("!defined({})", name)
+                }))
+            }
+            "elif" => {
+                TokenKind::HashElif(Ustr::from(&self.scan_directive_text()))
+            }
+            "else" => {
+                self.scan_directive_text();
+                TokenKind::HashElse
+            }
+            _ => {
+                self.scan_directive_text();
+                TokenKind::HashEndif
+            }
+        }
+    }
+
+    fn scan_include(&mut self) -> TokenKind {
+        let system = self.base.current() == '<';
         match self.base.scan_quote() {
+            TokenKind::String(n) if system => TokenKind::HashIncludeSystem(n),
             TokenKind::String(n) => TokenKind::HashInclude(n),
-            TokenKind::InvalidChar(_) => {
-                // sqlite.c has an unusual line
-                //    #  include  INC_STRINGIFY(SQLITE_CUSTOM_INCLUDE)
-                // Just ignore those for now
+            // An unterminated path at the end of the file
+            TokenKind::EndOfFile => TokenKind::EndOfFile,
+            // sqlite.c has an unusual line
+            //    #  include  INC_STRINGIFY(SQLITE_CUSTOM_INCLUDE)
+            // Just ignore those for now, as well as other computed includes.
+            _ => {
                 self.base.skip_to_eol();
                 TokenKind::HashInclude(Ustr::default())
             }
-            _ => panic!("Unexpected path after #include"),
+        }
+    }
+
+    /// Scan a string or character literal.  Unlike Ada, the quotes can be
+    /// escaped with a backslash.  Literals never extend past the end of the
+    /// line, so that an unterminated one doesn't hide the next directives.
+    fn scan_literal(&mut self) -> TokenKind {
+        let quote = self.base.current();
+        let mut text = String::new();
+        loop {
+            match self.base.scan_char() {
+                '\x00' | '\n' => break,
+                '\\' => {
+                    text.push('\\');
+                    match self.base.scan_char() {
+                        '\x00' | '\n' => break,
+                        c => text.push(c),
+                    }
+                }
+                c if c == quote => {
+                    self.base.scan_char();
+                    break;
+                }
+                c => text.push(c),
+            }
+        }
+        if quote == '"' {
+            TokenKind::String(Ustr::from(&text))
+        } else {
+            TokenKind::Character(text.chars().next().unwrap_or_default())
         }
     }
 }
@@ -120,8 +220,8 @@ impl Lexer for CppLexer<'_> {
             '-' => TokenKind::Minus,
             '(' => TokenKind::OpenParenthesis,
             ';' => TokenKind::Semicolon,
-            '#' => return self.scan_include(),
-            '"' => return self.base.scan_quote(),
+            '#' => return self.scan_directive(),
+            '"' | '\'' => return self.scan_literal(),
             _ if self.base.is_wordchar() => {
                 return self.scan_identifier_or_keyword();
             }
@@ -3,7 +3,7 @@ use crate::{
     cpp_lexer::CppLexer,
     errors::Error,
     qnames::QName,
-    sourcefile::{ParseResult, SourceKind},
+    sourcefile::{Include, ParseResult, SourceKind},
     tokens::TokenKind,
 };
 use std::path::Path;
@@ -24,30 +24,137 @@ impl<'a> CppScanner<'a> {
             )]),
             kind: SourceKind::Implementation,
             deps: Default::default(),
+            includes: Default::default(),
         };
 
+        // The conditionals we are in.  For each, the conditions of the
+        // previous branches (all false), and the condition of the current
+        // branch (None in the #else branch)
+        let mut conditionals: Vec<(Vec<Ustr>, Option<Ustr>)> = Vec::new();
+
         loop {
             match scan.base.peek() {
                 TokenKind::EndOfFile => break,
-                TokenKind::HashInclude(path) => {
-                    scan.base.next_token(); // consume keyword
-                    info.deps.insert(QName::new(vec![path]));
-                    Ok(())
+                TokenKind::HashInclude(path)
+                | TokenKind::HashIncludeSystem(path) => {
+                    let system = matches!(
+                        scan.base.peek(),
+                        TokenKind::HashIncludeSystem(_)
+                    );
+
+                    // Includes computed from macros are ignored
+                    if !path.is_empty() {
+                        let parts: Vec<String> = conditionals
+                            .iter()
+                            .flat_map(|(prior, current)| {
+                                prior
+                                    .iter()
+                                    .map(|p| format!("!({})", p))
+                                    .chain(current.map(|c| format!("({})", c)))
+                            })
+                            .collect();
+                        info.includes.push(Include {
+                            path,
+                            system,
+                            condition: (!parts.is_empty())
+                                .then(|| Ustr::from(&parts.join(" && "))),
+                        });
+                    }
+                }
+                TokenKind::HashIf(cond) => {
+                    conditionals.push((vec![], Some(cond)));
+                }
+                TokenKind::HashElif(cond) => {
+                    if let Some((prior, current)) = conditionals.last_mut() {
+                        prior.extend(current.replace(cond));
+                    }
+                }
+                TokenKind::HashElse => {
+                    if let Some((prior, current)) = conditionals.last_mut() {
+                        prior.extend(current.take());
+                    }
+                }
+                TokenKind::HashEndif => {
+                    conditionals.pop();
                 }
-                TokenKind::Identifier(_) => {
-                    // Stop parsing at the first function definition.  The
-                    // single identifier is likely a type.
-                    // ??? This is incorrect, there might be further includes
-                    // later
-                    break;
+                _ => {
+                    // The rest of the code has no impact on dependencies
                 }
-                t => Err(Error::wrong_token(
-                    "#include|#ifndef|#ifdef|#endif|#pragma",
-                    t,
-                )),
             }
-            .map_err(|e| scan.base.error_with_location(e))?;
+            scan.base.next_token();
         }
         Ok(info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cpp_lexer::CppLexer;
+    use crate::cpp_scanner::CppScanner;
+    use crate::files::File;
+    use std::path::Path;
+
+    #[test]
+    fn test_conditional_includes() {
+        let mut file = File::new_from_str(
+            r#"#ifndef A_H
+#include "a.h"
+int f(void) { return '"'; }
+#ifdef FOO  /* comment */
+#  include <foo.h>
+#elif defined(BAR) \
+   && BAR > 1
+#include "bar.h"
+#else
+#include "other.h"
+#endif
+#endif
+#include "last.h"
+"#,
+        );
+        let lex = CppLexer::new(&mut file).unwrap();
+        let info = CppScanner::parse(lex, Path::new("a.c")).unwrap();
+        let found: Vec<(&str, Option<&str>)> = info
+            .includes
+            .iter()
+            .map(|i| {
+                (i.path.as_str(), i.condition.as_ref().map(|c| c.as_str()))
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("a.h", Some("(!defined(A_H))")),
+                ("foo.h", Some("(!defined(A_H)) && (defined(FOO))")),
+                (
+                    "bar.h",
+                    Some(
+                        "(!defined(A_H)) && !(defined(FOO)) \
+                         && (defined(BAR) && BAR > 1)"
+                    )
+                ),
+                (
+                    "other.h",
+                    Some(
+                        "(!defined(A_H)) && !(defined(FOO)) \
+                         && !(defined(BAR) && BAR > 1)"
+                    )
+                ),
+                ("last.h", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_computed_includes() {
+        let mut file = File::new_from_str(
+            "#if 0\n#include MACRO\n#endif\n#include \"a.h\"\n\
+             #include \"unterminated",
+        );
+        let lex = CppLexer::new(&mut file).unwrap();
+        let info = CppScanner::parse(lex, Path::new("a.c")).unwrap();
+        let found: Vec<&str> =
+            info.includes.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(found, vec!["a.h"]);
+    }
+}
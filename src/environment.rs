@@ -1,30 +1,45 @@
 use crate::{
     ada_lexer::{AdaLexer, AdaLexerOptions},
     allscenarios::AllScenarios,
+    cache::Cache,
     errors::Error,
     gpr::GprFile,
     gpr_scanner::{GprPathToIndex, GprScanner},
     graph::{DepGraph, Edge, Node, NodeIndex},
+    preprocessor,
     qnames::QName,
     rawgpr::RawGPR,
     scenarios::Scenario,
     settings::Settings,
-    sourcefile::{SourceFile, SourceKind},
+    sourcefile::{ImportKind, SourceFile},
+};
+use path_clean::PathClean;
+use petgraph::{
+    visit::{Dfs, EdgeFiltered, EdgeRef},
+    Direction,
 };
-use petgraph::{visit::EdgeRef, Direction};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tracing::debug;
 use ustr::Ustr;
 
 type RawGPRs = HashMap<NodeIndex, RawGPR>;
-type UnitsMap = HashMap<QName, NodeIndex>;
+// For each unit name, the project that owns it and the unit node
+type UnitsMap = HashMap<QName, Vec<(Option<NodeIndex>, NodeIndex)>>;
 pub type GprMap = HashMap<PathBuf, GprFile>;
 
 // Maps files to details about the file.
-type SourceFilesMap = HashMap<PathBuf, Rc<RefCell<SourceFile>>>;
+pub type SourceFilesMap = HashMap<PathBuf, Rc<RefCell<SourceFile>>>;
+
+/// A dependency on a unit that is found in several of the visible projects
+pub struct AmbiguousImport {
+    pub source: PathBuf,
+    pub unit: QName,
+    pub projects: Vec<PathBuf>,
+}
 
 /// The whole set of gpr files
 #[derive(Default)]
@@ -35,9 +50,68 @@ pub struct Environment {
     pub files: SourceFilesMap,
     pub units: UnitsMap,
 
+    // Imports that could be resolved to units from several projects
+    pub ambiguous_imports: Vec<AmbiguousImport>,
+
+    // Number of parallel edges removed when merging scenarios
+    pub merged_edges: usize,
+
+    // Parse results of source files from previous runs
+    pub cache: Cache,
+
     implicit_projects: Vec<NodeIndex>,
 }
 
+/// The number of worker threads used to parse files
+fn worker_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Apply a function to all items, on a pool of worker threads.  Each worker
+/// takes the next item as soon as it is done with the previous one, which
+/// balances the load when some files are much larger than others.  The
+/// results are in the same order as the items.
+/// A worker that panics loses its results, which is reported as an error.
+fn parallel_map<T, R, F>(items: &[T], f: F) -> Result<Vec<R>, Error>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..worker_count().min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(idx) {
+                            None => return done,
+                            Some(item) => done.push((idx, f(item))),
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().ok())
+            .flatten()
+            .collect()
+    });
+    if results.len() != items.len() {
+        return Err(Error::WorkerPanicked {
+            missing: items.len() - results.len(),
+            total: items.len(),
+        });
+    }
+    results.sort_by_key(|(idx, _)| *idx);
+    Ok(results.into_iter().map(|(_, r)| r).collect())
+}
+
 impl Environment {
     /// Register a GPR file into the graph.
     /// Double-check it isn't there yet.
@@ -85,80 +159,291 @@ impl Environment {
     ) -> Result<RawGPRs, Error> {
         let mut rawfiles = RawGPRs::new();
 
+        let mut extends_all = Vec::new();
         let mut tovisit: Vec<(PathBuf, NodeIndex)> =
             gprs.iter().map(|(p, n)| (p.clone(), *n)).collect();
 
-        while let Some(visit) = tovisit.pop() {
-            let (path, nodeidx) = visit;
+        // Projects are parsed in waves: all the ones we know about are parsed
+        // in parallel, then the projects they import are parsed in the next
+        // wave.
+        while !tovisit.is_empty() {
+            let batch = std::mem::take(&mut tovisit);
+            for (nodeidx, raw) in parallel_map(&batch, |(path, nodeidx)| {
+                (*nodeidx, Environment::parse_raw_gpr(path, settings))
+            })? {
+                let raw = raw?;
+                self.add_raw_gpr_deps(
+                    nodeidx,
+                    &raw,
+                    gprs,
+                    &mut tovisit,
+                    &mut extends_all,
+                );
+                rawfiles.insert(nodeidx, raw);
+            }
+        }
+
+        for (nodeidx, extidx) in extends_all {
+            self.add_implicit_extensions(nodeidx, extidx);
+        }
+        Ok(rawfiles)
+    }
 
-            let mut file = crate::files::File::new(&path)?;
-            let options = AdaLexerOptions {
-                kw_aggregate: true,
-                kw_body: false,
-            };
-            let raw = GprScanner::parse(
-                AdaLexer::new(&mut file, options)?,
-                &path,
-                settings,
-            )?;
+    /// Parse a single project file
+    fn parse_raw_gpr(
+        path: &Path,
+        settings: &Settings,
+    ) -> Result<RawGPR, Error> {
+        let mut file = crate::files::File::new(path)?;
+        let options = AdaLexerOptions {
+            kw_aggregate: true,
+            kw_body: false,
+        };
+        GprScanner::parse(AdaLexer::new(&mut file, options)?, path, settings)
+    }
 
-            if !raw.is_abstract && !self.implicit_projects.contains(&nodeidx) {
-                for imp in &self.implicit_projects {
-                    self.graph.add_edge(nodeidx, *imp, Edge::GPRImports);
-                }
+    /// Add the dependencies of a newly parsed project to the graph.  Projects
+    /// that were not known yet are added to `tovisit`.
+    fn add_raw_gpr_deps(
+        &mut self,
+        nodeidx: NodeIndex,
+        raw: &RawGPR,
+        gprs: &mut GprPathToIndex,
+        tovisit: &mut Vec<(PathBuf, NodeIndex)>,
+        extends_all: &mut Vec<(NodeIndex, NodeIndex)>,
+    ) {
+        if !raw.is_abstract && !self.implicit_projects.contains(&nodeidx) {
+            for imp in &self.implicit_projects {
+                self.graph.add_edge(nodeidx, *imp, Edge::GPRImports);
             }
-            for dep in &raw.imported {
-                let depidx = match gprs.get(dep) {
-                    None => {
-                        let idx = self.register_gpr(dep.clone(), gprs);
-                        tovisit.push((dep.clone(), idx));
-                        idx
-                    }
-                    Some(depidx) => *depidx,
-                };
-                self.graph.add_edge(nodeidx, depidx, Edge::GPRImports);
+        }
+        for (dep, limited) in raw
+            .imported
+            .iter()
+            .map(|d| (d, false))
+            .chain(raw.limited_imported.iter().map(|d| (d, true)))
+        {
+            let depidx = match gprs.get(dep) {
+                None => {
+                    let idx = self.register_gpr(dep.clone(), gprs);
+                    tovisit.push((dep.clone(), idx));
+                    idx
+                }
+                Some(depidx) => *depidx,
+            };
+            self.graph.add_edge(
+                nodeidx,
+                depidx,
+                if limited {
+                    Edge::GPRLimitedImports
+                } else {
+                    Edge::GPRImports
+                },
+            );
+        }
+        if let Some(ref ext) = raw.extends {
+            let extidx = match gprs.get(ext) {
+                None => {
+                    let idx = self.register_gpr(ext.clone(), gprs);
+                    tovisit.push((ext.clone(), idx));
+                    idx
+                }
+                Some(extidx) => *extidx,
+            };
+            self.graph.add_edge(nodeidx, extidx, Edge::GPRExtends);
+            if raw.extends_all {
+                extends_all.push((nodeidx, extidx));
             }
-            if let Some(ref ext) = raw.extends {
-                let extidx = match gprs.get(ext) {
-                    None => {
-                        let idx = self.register_gpr(ext.clone(), gprs);
-                        tovisit.push((ext.clone(), idx));
-                        idx
-                    }
-                    Some(extidx) => *extidx,
-                };
-                self.graph.add_edge(nodeidx, extidx, Edge::GPRExtends);
+        }
+    }
+
+    /// When a project "extends all" another one, it implicitly extends every
+    /// project imported, directly or not, by the extended project, so that
+    /// its sources replace those from the whole tree.  This must be called
+    /// once all projects have been registered in the graph.
+    /// gprbuild creates a virtual project extending each of those projects,
+    /// and whose sources are the ones from the extending project.  Instead,
+    /// we let the extending project itself extend all of them, which is
+    /// enough for its units to override theirs.  Limited withs are not
+    /// followed, since the extended tree must not depend on the extending
+    /// project, and following them could create cycles.
+    fn add_implicit_extensions(
+        &mut self,
+        nodeidx: NodeIndex,
+        extidx: NodeIndex,
+    ) {
+        let filtered = EdgeFiltered::from_fn(&self.graph.0, |e| {
+            matches!(e.weight(), Edge::GPRImports | Edge::GPRExtends)
+        });
+        let mut implicit = Vec::new();
+        let mut dfs = Dfs::new(&filtered, extidx);
+        while let Some(n) = dfs.next(&filtered) {
+            if n != extidx
+                && n != nodeidx
+                && !self.implicit_projects.contains(&n)
+                && matches!(self.graph.0[n], Node::Project(_))
+            {
+                implicit.push(n);
             }
-            rawfiles.insert(nodeidx, raw);
         }
-        Ok(rawfiles)
+        for n in implicit {
+            self.graph.add_edge(nodeidx, n, Edge::GPRExtends);
+        }
+    }
+
+    /// Report a cycle in the project imports, with the line of each "with"
+    /// clause involved.
+    fn import_cycle_error(
+        &self,
+        cycle: &[NodeIndex],
+        rawfiles: &RawGPRs,
+    ) -> Error {
+        let mut result = Vec::new();
+        for (idx, nodeidx) in cycle.iter().enumerate() {
+            let next = cycle[(idx + 1) % cycle.len()];
+            let path = match self.graph.get_project(*nodeidx) {
+                Ok(p) => p,
+                Err(e) => return e,
+            };
+            // Implicit extensions from "extends all" are reported on the
+            // line of the extends clause.
+            let raw = &rawfiles[nodeidx];
+            let line = match self.graph.get_project(next) {
+                Ok(n) => raw
+                    .dep_lines
+                    .get(n)
+                    .or_else(|| {
+                        raw.extends.as_ref().and_then(|e| raw.dep_lines.get(e))
+                    })
+                    .copied(),
+                Err(e) => return e,
+            };
+            result.push((path.clone(), line));
+        }
+        Error::ProjectImportCycle(result)
     }
 
     /// Process the projects in topological order, so that any reference to a
     /// variable or attribute in another project is found.
-    fn process_projects(&mut self, rawfiles: RawGPRs) -> Result<GprMap, Error> {
+    /// The projects are grouped in levels, where each project only depends
+    /// on projects from the previous levels, and the projects of a level are
+    /// processed in parallel.  Each of them might declare new scenario
+    /// variables, so is processed with its own copy of self.scenarios, and
+    /// then merged back in a fixed order, so that the bits allocated for the
+    /// variables do not depend on thread scheduling.
+    /// Projects evaluated in a previous run are restored from the cache,
+    /// unless one of the projects they depend on had to be evaluated again.
+    fn process_projects(
+        &mut self,
+        rawfiles: RawGPRs,
+        settings: &Settings,
+    ) -> Result<GprMap, Error> {
         let mut gprs = GprMap::new();
-        for nodeidx in self.graph.toposort().iter().rev() {
-            let raw = &rawfiles[nodeidx];
-            let deps = self.graph.gpr_dependencies(*nodeidx);
-            let gprdeps: Vec<&GprFile> = deps
+        let mut restored: HashSet<NodeIndex> = HashSet::new();
+        let sorted = self
+            .graph
+            .toposort()
+            .map_err(|cycle| self.import_cycle_error(&cycle, &rawfiles))?;
+
+        let mut levels: Vec<Vec<NodeIndex>> = Vec::new();
+        let mut level_of: HashMap<NodeIndex, usize> = HashMap::new();
+        for nodeidx in sorted.iter().rev() {
+            let level = self
+                .graph
+                .gpr_dependencies(*nodeidx)
                 .iter()
-                .map(|i| self.graph.get_project(*i).map(|path| &gprs[path]))
-                .collect::<Result<Vec<_>, _>>()?;
-            let mut gpr = GprFile::new(
-                &raw.path,
-                raw.is_abstract,
-                raw.is_aggregate,
-                raw.is_library,
-                *nodeidx,
-            );
-            gpr.process(
-                raw,
-                raw.extends.as_ref().and_then(|e| gprs.get(e)),
-                &gprdeps,
-                &mut self.scenarios,
-            )?;
-            gprs.insert(raw.path.clone(), gpr);
+                .map(|d| level_of[d] + 1)
+                .max()
+                .unwrap_or(0);
+            level_of.insert(*nodeidx, level);
+            if levels.len() <= level {
+                levels.push(Vec::new());
+            }
+            levels[level].push(*nodeidx);
+        }
+
+        for level in levels {
+            let mut toprocess = Vec::new();
+            for nodeidx in level {
+                let raw = &rawfiles[&nodeidx];
+                let deps = self.graph.gpr_dependencies(nodeidx);
+                let cached = if settings.cache.is_some()
+                    && deps.iter().all(|d| restored.contains(d))
+                {
+                    let imports = self.project_imports(nodeidx);
+                    self.cache.get_gpr(&raw.path, &imports)
+                } else {
+                    None
+                };
+                match cached {
+                    Some(cached) => {
+                        let mut gpr = GprFile::new(
+                            &raw.path,
+                            raw.is_abstract,
+                            raw.is_aggregate,
+                            raw.is_library,
+                            nodeidx,
+                        );
+                        gpr.restore(raw, cached, &mut self.scenarios)?;
+                        gpr.referenced_gprs.extend(
+                            deps.iter().copied().filter(|d| {
+                                self.graph.get_project(*d).is_ok_and(|p| {
+                                    cached.referenced.contains(p)
+                                })
+                            }),
+                        );
+                        restored.insert(nodeidx);
+                        gprs.insert(raw.path.clone(), gpr);
+                    }
+                    None => toprocess.push(nodeidx),
+                }
+            }
+
+            let graph = &self.graph;
+            let scenarios = &self.scenarios;
+            let done = &gprs;
+            let processed = parallel_map(&toprocess, |nodeidx| {
+                let raw = &rawfiles[nodeidx];
+                let gprdeps: Vec<&GprFile> = graph
+                    .gpr_dependencies(*nodeidx)
+                    .iter()
+                    .map(|i| graph.get_project(*i).map(|path| &done[path]))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut gpr = GprFile::new(
+                    &raw.path,
+                    raw.is_abstract,
+                    raw.is_aggregate,
+                    raw.is_library,
+                    *nodeidx,
+                );
+                let mut local = scenarios.clone();
+                gpr.process(
+                    raw,
+                    raw.extends.as_ref().and_then(|e| done.get(e)),
+                    &gprdeps,
+                    &mut local,
+                )?;
+                Ok::<_, Error>((gpr, local))
+            })?;
+
+            for (nodeidx, res) in toprocess.iter().zip(processed) {
+                let (mut gpr, local) = res?;
+                gpr.merge_scenarios(&local, &mut self.scenarios)?;
+                if settings.cache.is_some() {
+                    let referenced = gpr
+                        .referenced_gprs
+                        .iter()
+                        .filter_map(|d| {
+                            self.graph.get_project(*d).ok().cloned()
+                        })
+                        .collect();
+                    self.cache.set_gpr(
+                        &rawfiles[nodeidx].path,
+                        gpr.cached(&self.scenarios, referenced),
+                    );
+                }
+                gprs.insert(rawfiles[nodeidx].path.clone(), gpr);
+            }
         }
         Ok(gprs)
     }
@@ -166,6 +451,9 @@ impl Environment {
     /// Create a new SourceFile, or return an existing one for the same path.
     /// It is an error if the same file has already been registered with
     /// different attributes.
+    /// The file is only parsed later, see parse_sources(), and its unit and
+    /// dependencies are only resolved once we know all the projects it
+    /// belongs to, see add_sources_to_graph().
     pub fn register_source(
         &mut self,
         path: &Path,
@@ -174,45 +462,7 @@ impl Environment {
         //  ??? Can we use raw_entry to avoid the clone
         let f = self.files.entry(path.into()).or_insert_with(|| {
             let sidx = self.graph.add_node(Node::Source(path.into()));
-            let mut s = SourceFile::new(path, lang, sidx)
-                .expect("Should deal with error");
-            if s.unitname != QName::default() {
-                let u = Environment::add_unit(
-                    &mut self.units,
-                    &mut self.graph,
-                    &s.unitname,
-                );
-                s.unit_node = Some(u);
-
-                // An implementation or separate depends on everything
-                // from the same unit, but the spec doesn't.
-                //    match s.kind {
-                //        SourceKind::Spec => {}
-                //        SourceKind::Implementation | SourceKind::Separate => {
-                //            self.graph.add_edge(s.file_node, u, Edge::SourceImports)
-                //        }
-                //    }
-            }
-            for dep in &s.deps {
-                Environment::add_source_import(
-                    &mut self.units,
-                    &mut self.graph,
-                    s.file_node,
-                    dep,
-                );
-            }
-
-            // Automatically depend on parent unit
-            if let Some(parent) = s.unitname.parent() {
-                Environment::add_source_import(
-                    &mut self.units,
-                    &mut self.graph,
-                    s.file_node,
-                    &parent,
-                );
-            }
-
-            Rc::new(RefCell::new(s))
+            Rc::new(RefCell::new(SourceFile::new(path, lang, sidx)))
         });
 
         if f.borrow().lang != lang {
@@ -222,35 +472,66 @@ impl Environment {
         }
     }
 
-    /// Add a unit to the graph, if not there yet
+    /// Parse all registered source files to find their unit and
+    /// dependencies.  Files that are not in the cache are parsed in
+    /// parallel, the results are then stored serially.
+    fn parse_sources(&mut self) -> Result<(), Error> {
+        let mut toparse = Vec::new();
+        for (path, file) in &self.files {
+            let mut f = file.borrow_mut();
+            match self.cache.get_source(path, f.lang) {
+                Some(info) => f.set_parse_result(info),
+                None => toparse.push((path.clone(), f.lang)),
+            }
+        }
+
+        let results = parallel_map(&toparse, |(path, lang)| {
+            SourceFile::parse(path, *lang)
+        })?;
+
+        let mut errors = Vec::new();
+        for ((path, lang), info) in toparse.into_iter().zip(results) {
+            match info {
+                Ok(info) => {
+                    self.cache.set_source(&path, lang, &info);
+                    self.files[&path].borrow_mut().set_parse_result(info);
+                }
+                Err(e) => errors.push((path, e)),
+            }
+        }
+
+        // Report errors in a deterministic order
+        errors.sort_by(|e1, e2| e1.0.cmp(&e2.0));
+        match errors.into_iter().next() {
+            None => Ok(()),
+            Some((path, error)) => Err(Error::WithPath {
+                path,
+                error: Box::new(error),
+            }),
+        }
+    }
+
+    /// Add a unit to the graph, if not there yet.
+    /// The same unit name might be used by multiple projects, so we have one
+    /// node per project.  Units which are imported, but not found in any of
+    /// the visible projects, have no owner.
     fn add_unit(
         units: &mut UnitsMap,
         graph: &mut DepGraph,
         unitname: &QName,
+        owner: Option<NodeIndex>,
     ) -> NodeIndex {
-        match units.get(unitname) {
-            Some(u) => *u,
+        let nodes = units.entry(unitname.clone()).or_default();
+        match nodes.iter().find(|(o, _)| *o == owner) {
+            Some((_, u)) => *u,
             None => {
-                units.insert(
-                    unitname.clone(),
-                    graph.add_node(Node::Unit(unitname.clone())),
-                );
-                *units.get(unitname).unwrap()
+                let u = graph.add_node(Node::Unit(unitname.clone()));
+                nodes.push((owner, u));
+                u
             }
         }
     }
 
-    /// Add a new dependency from the source to a given unit
-    fn add_source_import(
-        units: &mut UnitsMap,
-        graph: &mut DepGraph,
-        source: NodeIndex,
-        unit: &QName,
-    ) {
-        let u = Environment::add_unit(units, graph, unit);
-        graph.add_edge(source, u, Edge::SourceImports);
-    }
-
     /// Create graph nodes for the source files, and group the files into
     /// logical units.
     fn add_sources_to_graph(
@@ -262,31 +543,56 @@ impl Environment {
             let gpr = gprs.get_mut(&path).unwrap();
             for (scenario, sources) in gpr.sources.iter() {
                 for s in sources {
-                    let sm = s.file.borrow();
+                    let file = self.files[&s.path].clone();
+                    let mut sm = file.borrow_mut();
 
                     self.graph.add_edge(
                         gpridx,
                         sm.file_node,
-                        Edge::ProjectSource(*scenario),
+                        Edge::ProjectSource(scenario.clone()),
                     );
 
+                    // C and C++ files are grouped into units from the naming
+                    // scheme of the project.
+                    if sm.unit_node.is_none()
+                        && matches!(sm.lang.as_str(), "c" | "c++")
+                    {
+                        let basename = sm
+                            .path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string();
+                        if let Some((name, kind)) = gpr
+                            .naming
+                            .iter()
+                            .find(|(s, _)| {
+                                !self.scenarios.never_matches(&(*s & scenario))
+                            })
+                            .and_then(|(_, n)| n.c_unit(sm.lang, &basename))
+                        {
+                            sm.unitname = name;
+                            sm.kind = kind;
+                        }
+                    }
+
+                    // If the file belongs to several projects, its unit is
+                    // owned by the first one.
+                    if sm.unit_node.is_none() && sm.unitname != QName::default()
+                    {
+                        sm.unit_node = Some(Environment::add_unit(
+                            &mut self.units,
+                            &mut self.graph,
+                            &sm.unitname,
+                            Some(gpridx),
+                        ));
+                    }
+
                     if let Some(u) = sm.unit_node {
                         self.graph.add_edge(
                             u,
                             sm.file_node,
-                            match sm.kind {
-                                SourceKind::Spec => Edge::UnitSource((
-                                    SourceKind::Spec,
-                                    *scenario,
-                                )),
-                                SourceKind::Implementation => Edge::UnitSource(
-                                    (SourceKind::Implementation, *scenario),
-                                ),
-                                SourceKind::Separate => Edge::UnitSource((
-                                    SourceKind::Separate,
-                                    *scenario,
-                                )),
-                            },
+                            Edge::UnitSource((sm.kind, scenario.clone())),
                         );
                     }
                 }
@@ -295,6 +601,281 @@ impl Environment {
         Ok(())
     }
 
+    /// Find the files included by a C or C++ source file, for each of the
+    /// scenarios in which it is part of a project.  Each include is searched
+    /// in the directory of the including file (unless it is a system
+    /// include), then in the source directories of the project and the
+    /// projects it imports, and finally in the directories given by -I in
+    /// the compiler switches.  Includes inside preprocessor conditionals are
+    /// ignored when the -D and -U switches show the condition is false in
+    /// all parts of the scenario.
+    /// Returns the source nodes of the headers, and the includes that could
+    /// not be found.
+    fn resolve_includes(
+        &self,
+        sm: &SourceFile,
+        visible: &HashMap<NodeIndex, HashSet<NodeIndex>>,
+        gprs: &GprMap,
+        settings: &Settings,
+    ) -> (Vec<NodeIndex>, Vec<QName>) {
+        let mut headers = Vec::new();
+        let mut unresolved = Vec::new();
+        if sm.includes.is_empty() {
+            return (headers, unresolved);
+        }
+
+        let srcdir = sm.path.parent().unwrap_or(Path::new("/"));
+        let basename = Ustr::from(
+            &sm.path.file_name().unwrap_or_default().to_string_lossy(),
+        );
+
+        for e in self
+            .graph
+            .0
+            .edges_directed(sm.file_node, Direction::Incoming)
+        {
+            let Edge::ProjectSource(scenario) = e.weight() else {
+                continue;
+            };
+            let owner = e.source();
+            let mut projects: Vec<NodeIndex> = visible[&owner]
+                .iter()
+                .filter(|p| **p != owner)
+                .copied()
+                .collect();
+            projects.sort();
+            projects.insert(0, owner);
+
+            let mut search: Vec<&Path> = Vec::new();
+            for p in projects {
+                let Some(gpr) =
+                    self.graph.get_project(p).ok().and_then(|p| gprs.get(p))
+                else {
+                    continue;
+                };
+                for (s, dirs) in gpr.source_dirs.iter() {
+                    if !self.scenarios.never_matches(&(s & scenario)) {
+                        search.extend(dirs.iter().map(PathBuf::as_path));
+                    }
+                }
+            }
+            let ownergpr =
+                self.graph.get_project(owner).ok().and_then(|p| gprs.get(p));
+            let switches = ownergpr
+                .map(|gpr| {
+                    gpr.compiler_include_dirs(
+                        sm.lang,
+                        basename,
+                        scenario,
+                        &self.scenarios,
+                        settings,
+                    )
+                })
+                .unwrap_or_default();
+            search.extend(switches.iter().map(PathBuf::as_path));
+            let macros = ownergpr
+                .map(|gpr| {
+                    gpr.compiler_macros(
+                        sm.lang,
+                        basename,
+                        scenario,
+                        &self.scenarios,
+                    )
+                })
+                .unwrap_or_else(|| vec![Default::default()]);
+
+            for inc in &sm.includes {
+                // Skip includes that are known not to apply, but keep the
+                // ones whose condition cannot be evaluated.
+                if let Some(cond) = &inc.condition {
+                    if macros
+                        .iter()
+                        .all(|m| preprocessor::evaluate(cond, m) == Some(false))
+                    {
+                        continue;
+                    }
+                }
+                let found = (!inc.system)
+                    .then_some(srcdir)
+                    .into_iter()
+                    .chain(search.iter().copied())
+                    .find_map(|dir| {
+                        let p = dir.join(inc.path.as_str()).clean();
+                        self.files.get(&Environment::source_path(p, settings))
+                    });
+                match found {
+                    Some(f) => {
+                        let f = f.borrow().file_node;
+                        if !headers.contains(&f) {
+                            headers.push(f);
+                        }
+                    }
+                    None if !inc.system => {
+                        let name = QName::new(vec![inc.path]);
+                        if !unresolved.contains(&name) {
+                            unresolved.push(name);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        (headers, unresolved)
+    }
+
+    /// The key of a source file in `self.files`.  When resolving symbolic
+    /// links, the source directories are canonicalized but not the files
+    /// themselves, so we do the same here.
+    pub fn source_path(path: PathBuf, settings: &Settings) -> PathBuf {
+        if settings.resolve_symbolic_links {
+            if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+                if let Ok(dir) = dir.canonicalize() {
+                    return dir.join(name);
+                }
+            }
+        }
+        path
+    }
+
+    /// Resolve the dependencies of all source files to unit nodes, or to
+    /// header files for C and C++ includes.
+    /// A unit is only visible from a source file if it belongs to one of the
+    /// projects of that file, or to a project they import or extend,
+    /// directly or not.  When the unit is found in several of those projects,
+    /// the import is ambiguous: we depend on all of them, and report it.
+    /// When it isn't found in any of them, we fall back to the units from
+    /// the other projects.
+    fn resolve_source_imports(&mut self, gprs: &GprMap, settings: &Settings) {
+        let mut visible: HashMap<NodeIndex, HashSet<NodeIndex>> =
+            HashMap::new();
+        let mut extended: HashMap<NodeIndex, HashSet<NodeIndex>> =
+            HashMap::new();
+        for (gpridx, _) in self.graph.iter_project_nodes() {
+            visible.insert(gpridx, self.graph.visible_projects(gpridx));
+            extended.insert(gpridx, self.graph.extended_projects(gpridx));
+        }
+
+        let mut files: Vec<_> = self.files.values().cloned().collect();
+        files.sort_by(|f1, f2| f1.borrow().path.cmp(&f2.borrow().path));
+
+        for file in files {
+            let sm = file.borrow();
+            let from: HashSet<NodeIndex> = self
+                .graph
+                .projects_of_source(sm.file_node)
+                .iter()
+                .flat_map(|o| visible[o].iter().copied())
+                .collect();
+
+            let mut deps: HashMap<QName, ImportKind> = sm.deps.clone();
+
+            // C and C++ include directives are resolved directly to the
+            // header files.  The ones we cannot find are handled like other
+            // missing units.
+            let (headers, unresolved) =
+                self.resolve_includes(&sm, &visible, gprs, settings);
+            for u in headers {
+                self.graph.add_edge(
+                    sm.file_node,
+                    u,
+                    Edge::SourceImports(ImportKind::Regular),
+                );
+            }
+            deps.extend(
+                unresolved.into_iter().map(|u| (u, ImportKind::Regular)),
+            );
+
+            // Automatically depend on parent unit
+            if let Some(parent) = sm.unitname.parent() {
+                deps.insert(parent, ImportKind::Regular);
+            }
+
+            let mut deps: Vec<(QName, ImportKind)> = deps.into_iter().collect();
+            deps.sort();
+
+            for (dep, kind) in deps {
+                let mut candidates: Vec<(NodeIndex, NodeIndex)> = self
+                    .units
+                    .get(&dep)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(owner, u)| match owner {
+                        Some(o) if from.contains(o) => Some((*o, *u)),
+                        _ => None,
+                    })
+                    .collect();
+
+                // A project that extends another one overrides its units
+                let all = candidates.clone();
+                candidates.retain(|(o, _)| {
+                    !all.iter().any(|(o2, _)| extended[o2].contains(o))
+                });
+
+                match candidates.len() {
+                    0 => {
+                        // The unit is not visible.  The project tree is
+                        // incorrect (see ActionCheck::bypassed_withs), but
+                        // we still depend on the units from other projects.
+                        let owned: Vec<NodeIndex> = self
+                            .units
+                            .get(&dep)
+                            .into_iter()
+                            .flatten()
+                            .filter(|(owner, _)| owner.is_some())
+                            .map(|(_, u)| *u)
+                            .collect();
+                        if owned.is_empty() {
+                            let u = Environment::add_unit(
+                                &mut self.units,
+                                &mut self.graph,
+                                &dep,
+                                None,
+                            );
+                            self.graph.add_edge(
+                                sm.file_node,
+                                u,
+                                Edge::SourceImports(kind),
+                            );
+                        }
+                        for u in owned {
+                            self.graph.add_edge(
+                                sm.file_node,
+                                u,
+                                Edge::SourceImports(kind),
+                            );
+                        }
+                    }
+                    1 => {
+                        self.graph.add_edge(
+                            sm.file_node,
+                            candidates[0].1,
+                            Edge::SourceImports(kind),
+                        );
+                    }
+                    _ => {
+                        let mut projects = Vec::new();
+                        for (o, u) in candidates {
+                            self.graph.add_edge(
+                                sm.file_node,
+                                u,
+                                Edge::SourceImports(kind),
+                            );
+                            if let Ok(p) = self.graph.get_project(o) {
+                                projects.push(p.clone());
+                            }
+                        }
+                        projects.sort();
+                        self.ambiguous_imports.push(AmbiguousImport {
+                            source: sm.path.clone(),
+                            unit: dep,
+                            projects,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
     /// From a list of unit nodes, return the paths of all source files.
     /// We return a set, since the same file might be visible in multiple
     /// scenarios.
@@ -327,6 +908,8 @@ impl Environment {
     /// This function bypasses the file nodes, and returns the dependencies
     /// between units (so we get tuples like (A, B) in the example above).
     /// Iteration starts from a set of target units (B in the example above)
+    /// In C, files include one of the headers of the unit rather than the
+    /// unit itself, and this is also a dependency on the unit.
     pub fn iter_unit_deps<'a, I>(
         &'a self,
         targets: I,
@@ -336,11 +919,24 @@ impl Environment {
     {
         targets
             .flat_map(|unit| {
+                std::iter::once(unit)
+                    .chain(
+                        self.graph
+                            .0
+                            .edges_directed(unit, Direction::Outgoing)
+                            .filter(|e| {
+                                matches!(e.weight(), Edge::UnitSource(_))
+                            })
+                            .map(|e| e.target()),
+                    )
+                    .map(move |imported| (imported, unit))
+            })
+            .flat_map(|(imported, unit)| {
                 self.graph
                     .0
-                    .edges_directed(unit, Direction::Incoming)
+                    .edges_directed(imported, Direction::Incoming)
                     .filter_map(move |e| match e.weight() {
-                        Edge::SourceImports => Some((e.source(), unit)),
+                        Edge::SourceImports(_) => Some((e.source(), unit)),
                         _ => None,
                     })
             })
@@ -358,10 +954,14 @@ impl Environment {
     /// Recursively look for all project files, parse them and prepare the
     /// dependency graph.
     pub fn parse_all(&mut self, settings: &Settings) -> Result<(), Error> {
+        if let Some(cachefile) = &settings.cache {
+            self.cache = Cache::load(cachefile);
+        }
+
         let mut gprindexes: GprPathToIndex = self.find_all_gpr(settings);
         let rawfiles: RawGPRs =
             self.parse_raw_gprs(&mut gprindexes, settings)?;
-        let mut gprmap: GprMap = self.process_projects(rawfiles)?;
+        let mut gprmap: GprMap = self.process_projects(rawfiles, settings)?;
 
         let mut all_source_dirs = HashSet::new();
         for gpr in gprmap.values_mut() {
@@ -369,26 +969,70 @@ impl Environment {
                 gpr.trim();
             }
             gpr.resolve_source_dirs(&mut all_source_dirs, settings)?;
-            gpr.resolve_naming(&mut self.scenarios);
+            gpr.resolve_naming(&mut self.scenarios)?;
             gpr.resolve_source_files(self, &all_source_dirs);
             debug!("gpr {:?}", gpr);
         }
 
+        self.parse_sources()?;
+
         // One we have processed everything, another pass
         for gpr in gprmap.values() {
             gpr.resolve_library_interface(
                 &mut self.scenarios,
                 &gprmap,
+                &self.files,
                 settings,
             );
         }
 
         self.add_sources_to_graph(gprindexes, &mut gprmap)?;
+        self.resolve_source_imports(&gprmap, settings);
+        self.merged_edges = self.graph.merge_parallel_edges(&self.scenarios);
 
         self.gprs = gprmap;
+
+        if let Some(cachefile) = &settings.cache {
+            self.update_cache();
+            self.cache.save(cachefile)?;
+        }
         Ok(())
     }
 
+    /// The projects imported or extended by a project, sorted
+    fn project_imports(&self, gprnode: NodeIndex) -> Vec<PathBuf> {
+        let mut imports: Vec<PathBuf> = self
+            .graph
+            .0
+            .neighbors(gprnode)
+            .filter_map(|i| self.graph.get_project(i).ok().cloned())
+            .collect();
+        imports.sort();
+        imports.dedup();
+        imports
+    }
+
+    /// Record the current state of all projects in the cache
+    fn update_cache(&mut self) {
+        self.cache.retain_sources(|p| self.files.contains_key(p));
+        self.cache.retain_projects(|p| self.gprs.contains_key(p));
+        for gpr in self.gprs.values() {
+            let Ok(path) = self.graph.get_project(gpr.node) else {
+                continue;
+            };
+            let imports = self.project_imports(gpr.node);
+            let mut sources: Vec<PathBuf> = gpr
+                .sources
+                .iter()
+                .flat_map(|(_, files)| files.iter())
+                .map(|f| f.path.clone())
+                .collect();
+            sources.sort();
+            sources.dedup();
+            self.cache.set_project(path, imports, sources);
+        }
+    }
+
     /// Find all scenarios that result in different values in the project
     pub fn find_used_scenarios(&self, scenars: &mut HashSet<Scenario>) {
         for g in self.gprs.values() {
@@ -396,8 +1040,453 @@ impl Environment {
         }
     }
 
+    /// Whether the project is implicitly imported by all others (--runtime)
+    pub fn is_implicit_project(&self, gprnode: NodeIndex) -> bool {
+        self.implicit_projects.contains(&gprnode)
+    }
+
     /// Retrieve the node for a project node
     pub fn get_gpr(&self, gprpath: &Path) -> Option<&GprFile> {
         self.gprs.get(gprpath)
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        environment::{parallel_map, Environment},
+        errors::Error,
+        graph::{Edge, Node},
+        packagename::PackageName,
+        settings::Settings,
+    };
+    use petgraph::{visit::EdgeRef, Direction};
+    use std::path::{Path, PathBuf};
+
+    /// A temporary directory, unique to a test and to the process.  It is
+    /// removed when dropped, even if the test fails.
+    pub struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Result<Self, Error> {
+            let dir = std::env::temp_dir().join(format!(
+                "gprdeps_{}_{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir)?;
+            Ok(TempDir(dir))
+        }
+
+        /// Create files in the directory
+        pub fn write(&self, files: &[(&str, &str)]) -> Result<(), Error> {
+            for (path, content) in files {
+                let path = self.0.join(path);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(path, content)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Create the given files in a temporary directory, and parse all the
+    /// projects found there.  Paths are displayed relative to that
+    /// directory, which is removed when the returned guard is dropped.
+    pub fn parse_tree(
+        name: &str,
+        files: &[(&str, &str)],
+    ) -> Result<(Environment, Settings, TempDir), Error> {
+        let dir = TempDir::new(name)?;
+        dir.write(files)?;
+        let settings = Settings {
+            root: vec![dir.0.clone()],
+            relto: dir.0.clone(),
+            ..Default::default()
+        };
+        let mut env = Environment::default();
+        env.parse_all(&settings)?;
+        Ok((env, settings, dir))
+    }
+
+    /// The units and files imported by a source file
+    pub fn imports_of(
+        env: &Environment,
+        settings: &Settings,
+        path: &str,
+    ) -> Vec<String> {
+        let file = env.files[&settings.relto.join(path)].borrow();
+        let mut result: Vec<String> = env
+            .graph
+            .0
+            .edges_directed(file.file_node, Direction::Outgoing)
+            .filter(|e| matches!(e.weight(), Edge::SourceImports(_)))
+            .map(|e| match &env.graph.0[e.target()] {
+                Node::Unit(u) => u.to_string(),
+                Node::Source(p) | Node::Project(p) => {
+                    settings.display_path(p).to_string()
+                }
+            })
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn resolve_includes() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "includes",
+            &[
+                (
+                    "c.gpr",
+                    "project C is
+                        for Languages use (\"C\");
+                        for Source_Dirs use (\"include\", \"src\");
+                     end C;",
+                ),
+                (
+                    "src/main.c",
+                    "#include \"config.h\"\n#include <config.h>\n\
+                     #include \"shared.h\"\n#include \"missing.h\"\n\
+                     #include <stdio.h>\nint main() {}\n",
+                ),
+                ("src/config.h", "int a;\n"),
+                ("include/config.h", "int b;\n"),
+                ("include/shared.h", "int c;\n"),
+            ],
+        )?;
+
+        // A quoted include is first searched next to the including file,
+        // then in the source directories.  Missing system includes are
+        // ignored.
+        assert_eq!(
+            imports_of(&env, &settings, "src/main.c"),
+            vec![
+                "include/config.h",
+                "include/shared.h",
+                "missing.h",
+                "src/config.h",
+            ],
+        );
+        assert!(matches!(
+            env.graph.0[env.files
+                [Path::new(&settings.relto.join("include/shared.h"))]
+            .borrow()
+            .file_node],
+            Node::Source(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn extends_all() -> Result<(), Error> {
+        // Y has a limited with on W, which itself depends on X.  X must not
+        // implicitly extend W, or this would be a cycle.
+        let (env, settings, _dir) = parse_tree(
+            "extends_all",
+            &[
+                ("x.gpr", "project X extends all \"y\" is end X;"),
+                (
+                    "y.gpr",
+                    "with \"z\"; limited with \"w\"; project Y is end Y;",
+                ),
+                ("z.gpr", "project Z is end Z;"),
+                ("w.gpr", "with \"x\"; project W is end W;"),
+            ],
+        )?;
+        let x = env.gprs[&settings.relto.join("x.gpr")].node;
+        let mut extended: Vec<String> = env
+            .graph
+            .extended_projects(x)
+            .into_iter()
+            .map(|p| {
+                settings
+                    .display_path(env.graph.get_project(p).unwrap())
+                    .to_string()
+            })
+            .collect();
+        extended.sort();
+        assert_eq!(extended, vec!["y.gpr", "z.gpr"]);
+        Ok(())
+    }
+
+    #[test]
+    fn import_cycle() -> Result<(), Error> {
+        // Z imports X, and X implicitly extends Z since it is imported by Y.
+        let files = [
+            ("x.gpr", "project X\n   extends all \"y\" is end X;"),
+            ("y.gpr", "with \"z\"; project Y is end Y;"),
+            ("z.gpr", "with \"x\"; project Z is end Z;"),
+        ];
+        let Err(err) = parse_tree("import_cycle", &files) else {
+            panic!("expected an import cycle");
+        };
+        let dir = std::env::temp_dir()
+            .join(format!("gprdeps_import_cycle_{}", std::process::id()));
+        let cycle = format!(
+            "Cycle in project imports: {}:2 -> {}:1 -> {}",
+            dir.join("x.gpr").display(),
+            dir.join("z.gpr").display(),
+            dir.join("x.gpr").display(),
+        );
+        let cycle2 = format!(
+            "Cycle in project imports: {}:1 -> {}:2 -> {}",
+            dir.join("z.gpr").display(),
+            dir.join("x.gpr").display(),
+            dir.join("z.gpr").display(),
+        );
+        assert!(
+            err.to_string() == cycle || err.to_string() == cycle2,
+            "{}",
+            err
+        );
+
+        // Links without a with clause, like the implicit imports of the
+        // runtime projects, are shown without a line
+        assert_eq!(
+            Error::ProjectImportCycle(vec![
+                ("a.gpr".into(), Some(3)),
+                ("rts.gpr".into(), None),
+            ])
+            .to_string(),
+            "Cycle in project imports: a.gpr:3 -> rts.gpr -> a.gpr"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cached_projects() -> Result<(), Error> {
+        let dir = TempDir::new("cached_projects")?;
+        dir.write(&[
+            (
+                "a.gpr",
+                "with \"b\";\nproject A is\n\
+                 for Source_Dirs use (\"a\");\nend A;",
+            ),
+            ("a/a.ads", "package A is end A;"),
+            (
+                "b.gpr",
+                "project B is\n\
+                 type T is (\"x\", \"y\");\n\
+                 M : T := external (\"GPRDEPS_CACHED_MODE\", \"x\");\n\
+                 case M is\n\
+                 when \"x\" => for Source_Dirs use (\"b\");\n\
+                 when \"y\" => for Source_Dirs use (\"b\", \"by\");\n\
+                 end case;\nend B;",
+            ),
+            ("b/b.ads", "package B is end B;"),
+            ("by/b2.ads", "package B2 is end B2;"),
+            (
+                "c.gpr",
+                "project C is\n for Source_Dirs use (\"c\");\nend C;",
+            ),
+            ("c/c.ads", "package C is end C;"),
+        ])?;
+        let settings = Settings {
+            root: vec![dir.0.clone()],
+            relto: dir.0.clone(),
+            cache: Some(dir.0.join("cache.json")),
+            ..Default::default()
+        };
+        let parse = || -> Result<Environment, Error> {
+            let mut env = Environment::default();
+            env.parse_all(&settings)?;
+            Ok(env)
+        };
+        let sources_of = |env: &Environment, gpr: &str| -> Vec<String> {
+            let gpr = env.get_gpr(&dir.0.join(gpr)).unwrap();
+            let mut result: Vec<String> = gpr
+                .sources
+                .iter()
+                .map(|(s, files)| {
+                    let mut names: Vec<String> = files
+                        .iter()
+                        .map(|f| settings.display_path(&f.path).to_string())
+                        .collect();
+                    names.sort();
+                    format!("{} {}", env.scenarios.describe(s), names.join(","))
+                })
+                .collect();
+            result.sort();
+            result
+        };
+
+        let env = parse()?;
+        assert_eq!((env.cache.parsed, env.cache.reused), (4, 0));
+        assert_eq!((env.cache.gprs_evaluated, env.cache.gprs_reused), (3, 0));
+        let sources_b = sources_of(&env, "b.gpr");
+        assert_eq!(sources_b.len(), 2);
+
+        // Nothing changed: the projects are not evaluated again
+        let env = parse()?;
+        assert_eq!((env.cache.parsed, env.cache.reused), (0, 4));
+        assert_eq!((env.cache.gprs_evaluated, env.cache.gprs_reused), (0, 3));
+        assert_eq!(sources_of(&env, "b.gpr"), sources_b);
+
+        // Editing an imported project also evaluates the importing project
+        // again, and parses its sources
+        let b = dir.0.join("b.gpr");
+        let mut content = std::fs::read_to_string(&b)?;
+        content.push_str("\n--  edited\n");
+        std::fs::write(&b, content)?;
+        let env = parse()?;
+        assert_eq!((env.cache.parsed, env.cache.reused), (3, 1));
+        assert_eq!((env.cache.gprs_evaluated, env.cache.gprs_reused), (2, 1));
+        assert_eq!(sources_of(&env, "b.gpr"), sources_b);
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_projects() -> Result<(), Error> {
+        // A and B are processed in parallel, and both declare scenario
+        // variables.  C sees the values of both with consistent scenarios.
+        let (env, settings, _dir) = parse_tree(
+            "parallel_projects",
+            &[
+                (
+                    "a.gpr",
+                    "project A is\n\
+                     type T is (\"on\", \"off\");\n\
+                     V : T := external (\"GPRDEPS_PARALLEL_A\", \"on\");\n\
+                     type S is (\"x\", \"y\");\n\
+                     Shared : S := external (\"GPRDEPS_PARALLEL_S\", \"x\");\n\
+                     for Source_Dirs use ();\n\
+                     end A;",
+                ),
+                (
+                    "b.gpr",
+                    "project B is\n\
+                     type T is (\"1\", \"2\", \"3\");\n\
+                     W : T := external (\"GPRDEPS_PARALLEL_B\", \"1\");\n\
+                     type S is (\"x\", \"y\");\n\
+                     Shared : S := external (\"GPRDEPS_PARALLEL_S\");\n\
+                     for Source_Dirs use ();\n\
+                     end B;",
+                ),
+                (
+                    "c.gpr",
+                    "with \"a\";\nwith \"b\";\nproject C is\n\
+                     Name := A.V & \"-\" & B.W & \"-\" & B.Shared;\n\
+                     for Source_Dirs use ();\n\
+                     end C;",
+                ),
+            ],
+        )?;
+        let mut expected = Vec::new();
+        for a in ["off", "on"] {
+            for b in ["1", "2", "3"] {
+                for s in ["x", "y"] {
+                    expected.push(format!(
+                        "GPRDEPS_PARALLEL_A={a},GPRDEPS_PARALLEL_B={b},\
+                         GPRDEPS_PARALLEL_S={s}{:width$}{a}-{b}-{s}",
+                        "",
+                        width = 4 - a.len(),
+                    ));
+                }
+            }
+        }
+        let c = env.get_gpr(&settings.relto.join("c.gpr")).unwrap();
+        crate::gpr::tests::assert_variable(
+            c,
+            PackageName::None,
+            "name",
+            &env.scenarios,
+            &expected.join("\n"),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_jobs() {
+        let items: Vec<usize> = (0..100).collect();
+        let doubled: Vec<usize> = items.iter().map(|i| i * 2).collect();
+        assert_eq!(parallel_map(&items, |i| i * 2).unwrap(), doubled);
+
+        // The results of a worker that panics are lost
+        let res = parallel_map(&items, |i| {
+            if *i == 42 {
+                panic!("worker failed");
+            }
+            *i
+        });
+        assert!(matches!(
+            res,
+            Err(Error::WorkerPanicked {
+                missing: 1..,
+                total: 100
+            })
+        ));
+    }
+
+    #[test]
+    fn visible_units() -> Result<(), Error> {
+        // Two vendored copies of Utils, in projects that do not import each
+        // other.
+        let (env, settings, _dir) = parse_tree(
+            "visible_units",
+            &[
+                ("v1/v1.gpr", "project V1 is end V1;"),
+                ("v1/utils.ads", "package Utils is end Utils;"),
+                ("v2/v2.gpr", "project V2 is end V2;"),
+                ("v2/utils.ads", "package Utils is end Utils;"),
+                ("a/a.gpr", "with \"../v1/v1\"; project A is end A;"),
+                ("a/a.adb", "with Utils; procedure A is begin null; end A;"),
+                (
+                    "b/b.gpr",
+                    "with \"../v1/v1\"; with \"../v2/v2\";
+                     project B is end B;",
+                ),
+                ("b/b.adb", "with Utils; procedure B is begin null; end B;"),
+            ],
+        )?;
+
+        // The projects owning the units imported by a file
+        let owners = |path: &str| {
+            let file = env.files[&settings.relto.join(path)].borrow();
+            let mut owners: Vec<String> = env
+                .graph
+                .0
+                .edges_directed(file.file_node, Direction::Outgoing)
+                .filter(|e| matches!(e.weight(), Edge::SourceImports(_)))
+                .flat_map(|e| {
+                    env.units
+                        .values()
+                        .flatten()
+                        .filter(move |(_, u)| *u == e.target())
+                        .filter_map(|(o, _)| *o)
+                })
+                .map(|p| match &env.graph.0[p] {
+                    Node::Project(p) => settings.display_path(p).to_string(),
+                    _ => panic!("owner must be a project"),
+                })
+                .collect();
+            owners.sort();
+            owners
+        };
+
+        // Only the copy from the withed project is visible
+        assert_eq!(owners("a/a.adb"), vec!["v1/v1.gpr"]);
+
+        // Both copies are visible: this is ambiguous
+        assert_eq!(owners("b/b.adb"), vec!["v1/v1.gpr", "v2/v2.gpr"]);
+        let ambiguous: Vec<String> = env
+            .ambiguous_imports
+            .iter()
+            .map(|amb| {
+                format!(
+                    "{} {} {}",
+                    settings.display_path(&amb.source),
+                    amb.unit,
+                    amb.projects.len()
+                )
+            })
+            .collect();
+        assert_eq!(ambiguous, vec!["b/b.adb utils 2"]);
+        Ok(())
+    }
+}
@@ -21,15 +21,6 @@ pub enum Error {
     #[error("Expected {expected}, got {got}")]
     WrongToken { expected: String, got: String },
 
-    #[error("Cannot parse {path}, language {lang}")]
-    CannotParse {
-        path: std::path::PathBuf,
-        lang: String,
-    },
-
-    #[error("Invalid package name {0}")]
-    InvalidPackageName(Ustr),
-
     #[error("Invalid attribute name {0}")]
     InvalidAttribute(Ustr),
 
@@ -48,6 +39,12 @@ pub enum Error {
     #[error("Unknown function {0}")]
     UnknownFunction(Ustr),
 
+    #[error("Wrong arguments for function {0}")]
+    WrongFunctionArguments(Ustr),
+
+    #[error("Invalid regular expression {0}: {1}")]
+    InvalidRegexp(Ustr, String),
+
     #[error("`Project'` must be followed by attribute name")]
     MissingAttributeNameAfterProject,
 
@@ -66,12 +63,30 @@ pub enum Error {
     #[error("Wrong use of &")]
     WrongAmpersand,
 
-    #[error("Too many scenario variables or values for them")]
-    TooManyScenarioVariables,
-
     #[error("{0} not found")]
     NotFound(String),
 
+    #[error("Project {0} not found in {}",
+            itertools::join(.1.iter().map(|p| p.display()), ", "))]
+    ProjectNotFound(Ustr, Vec<std::path::PathBuf>),
+
+    #[error("Cycle in project imports: {}",
+            itertools::join(
+                .0.iter()
+                    .map(|(p, line)| match line {
+                        Some(line) => format!("{}:{}", p.display(), line),
+                        None => p.display().to_string(),
+                    })
+                    .chain(.0.first().map(|(p, _)| p.display().to_string())),
+                " -> "))]
+    ProjectImportCycle(Vec<(std::path::PathBuf, Option<u32>)>),
+
+    #[error("Cycle in the dependencies of {0}")]
+    SourceCycle(std::path::PathBuf),
+
+    #[error("{missing} of {total} files were not parsed: a worker panicked")]
+    WorkerPanicked { missing: usize, total: usize },
+
     #[error("File {0} registered twice with different languages")]
     InconsistentFileLang(std::path::PathBuf),
 
@@ -91,6 +106,18 @@ pub enum Error {
         source: std::fmt::Error,
     },
 
+    #[error("{source}")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+
+    #[error("Cannot write cache {0}: {1}")]
+    CannotWriteCache(std::path::PathBuf, String),
+
+    #[error("git failed: {0}")]
+    GitFailed(String),
+
     #[error("Invalid graph node type {0}")]
     InvalidGraphNode(String),
 }
@@ -0,0 +1,176 @@
+use crate::{
+    errors::Error,
+    files::File,
+    qnames::QName,
+    scanners::{parse_hash_include, LanguageScanner},
+    sourcefile::{ImportKind, Include, ParseResult, SourceKind},
+};
+use std::path::Path;
+use ustr::Ustr;
+
+// Modules provided by the compiler, which are never found in projects
+const INTRINSIC_MODULES: [&str; 5] = [
+    "iso_c_binding",
+    "iso_fortran_env",
+    "ieee_arithmetic",
+    "ieee_exceptions",
+    "ieee_features",
+];
+
+/// Scanner for Fortran files.  The unit is the first module declared in the
+/// file (or the file itself when it declares none), and it depends on the
+/// modules it uses and the files it includes.
+pub struct FortranScanner;
+
+/// If the statement starts with the given keyword (case-insensitive), return
+/// the rest of the statement.
+fn keyword<'a>(stmt: &'a str, kw: &str) -> Option<&'a str> {
+    let rest = stmt.get(kw.len()..)?;
+    if stmt[..kw.len()].eq_ignore_ascii_case(kw)
+        && !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+    {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// The identifier at the start of the string, lower-cased
+fn identifier(s: &str) -> Option<Ustr> {
+    let end = s
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(s.len());
+    (end > 0).then(|| Ustr::from(&s[..end].to_lowercase()))
+}
+
+impl LanguageScanner for FortranScanner {
+    fn parse(
+        &self,
+        file: &mut File,
+        path: &Path,
+    ) -> Result<ParseResult, Error> {
+        let mut info = ParseResult {
+            unitname: QName::default(),
+            kind: SourceKind::Implementation,
+            deps: Default::default(),
+            includes: Default::default(),
+        };
+        let fixed_form = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("f" | "F" | "for" | "FOR" | "f77" | "F77" | "ftn")
+        );
+        let mut modules = Vec::new();
+        let mut used = Vec::new();
+
+        for line in file.as_mut_str().lines() {
+            if fixed_form && line.starts_with(['c', 'C', '*']) {
+                continue; // comment line
+            }
+            if let Some(inc) = parse_hash_include(line) {
+                info.includes.push(inc);
+                continue;
+            }
+            let code = line.split('!').next().unwrap_or_default();
+            for stmt in code.split(';').map(str::trim) {
+                if let Some(rest) = keyword(stmt, "use") {
+                    // use [, intrinsic] :: name [, only: ...]
+                    let rest = match rest.strip_prefix(',') {
+                        Some(nature) => {
+                            let (nature, name) =
+                                nature.split_once("::").unwrap_or_default();
+                            if nature.trim().eq_ignore_ascii_case("intrinsic") {
+                                continue;
+                            }
+                            name.trim_start()
+                        }
+                        None => rest.trim_start_matches(':').trim_start(),
+                    };
+                    if let Some(name) = identifier(rest) {
+                        if !INTRINSIC_MODULES.contains(&name.as_str()) {
+                            used.push(name);
+                        }
+                    }
+                } else if let Some(rest) = keyword(stmt, "include") {
+                    let quote = rest.chars().next();
+                    if let Some(q @ ('\'' | '"')) = quote {
+                        if let Some(path) = rest[1..].split(q).next() {
+                            info.includes.push(Include {
+                                path: Ustr::from(path),
+                                system: false,
+                                condition: None,
+                            });
+                        }
+                    }
+                } else if let Some(rest) = keyword(stmt, "submodule") {
+                    // submodule (ancestor[:parent]) name
+                    if let Some(name) = rest
+                        .strip_prefix('(')
+                        .and_then(|r| identifier(r.trim()))
+                    {
+                        used.push(name);
+                    }
+                } else if let Some(rest) = keyword(stmt, "module") {
+                    // Skip "module procedure", and separate module procedures
+                    match identifier(rest) {
+                        Some(n)
+                            if n != "procedure"
+                                && n != "function"
+                                && n != "subroutine" =>
+                        {
+                            modules.push(n)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        info.unitname = match modules.first() {
+            Some(m) => QName::new(vec![*m]),
+            None => QName::new(vec![Ustr::from(&path.to_string_lossy())]),
+        };
+        for u in used {
+            if !modules.contains(&u) {
+                info.add_dep(QName::new(vec![u]), ImportKind::Regular);
+            }
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::files::File;
+    use crate::fortran_scanner::FortranScanner;
+    use crate::qnames::QName;
+    use crate::scanners::LanguageScanner;
+    use std::path::Path;
+    use ustr::Ustr;
+
+    #[test]
+    fn test_modules() {
+        let mut file = File::new_from_str(
+            "! A comment: use nothing
+module Solver
+   use, intrinsic :: iso_fortran_env
+   use iso_c_binding
+   use :: Linalg, only: dot  ! comment
+   use io_utils; use solver
+   include 'params.inc'
+contains
+   module procedure solve
+end module Solver
+",
+        );
+        let info = FortranScanner
+            .parse(&mut file, Path::new("solver.f90"))
+            .unwrap();
+        assert_eq!(info.unitname, QName::new(vec![Ustr::from("solver")]));
+        let mut deps: Vec<String> =
+            info.deps.keys().map(|d| d.to_string()).collect();
+        deps.sort();
+        assert_eq!(deps, vec!["io_utils", "linalg"]);
+        assert_eq!(info.includes.len(), 1);
+        assert_eq!(info.includes[0].path, "params.inc");
+    }
+}
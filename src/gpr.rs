@@ -1,22 +1,25 @@
 use crate::{
     allscenarios::AllScenarios,
+    cache::{strings_to_ustrs, ustrs_to_strings, CachedGpr, CachedValue},
     directory::Directory,
-    environment::{Environment, GprMap},
+    environment::{Environment, GprMap, SourceFilesMap},
     errors::Error,
     graph::NodeIndex,
     naming::{FileInGPR, Naming},
-    packagename::{PackageName, PACKAGE_NAME_VARIANTS},
+    packagename::PackageName,
     perscenario::PerScenario,
+    preprocessor::Macros,
     qnames::QName,
     qualifiedname::QualifiedName,
     rawexpr::{Statement, StatementList},
     rawgpr::RawGPR,
     scenarios::Scenario,
     settings::Settings,
-    simplename::SimpleName,
+    simplename::{SimpleName, StringOrOthers},
     values::ExprValue,
 };
 use path_clean::PathClean;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use ustr::Ustr;
@@ -35,6 +38,14 @@ lazy_static::lazy_static! {
     static ref CST_EXT_C: Ustr = Ustr::from(".c");
     static ref CST_EXT_HH: Ustr = Ustr::from(".hh");
     static ref CST_EXT_CPP: Ustr = Ustr::from(".cpp");
+    static ref CST_FORTRAN: Ustr = Ustr::from("fortran");
+    static ref CST_EXT_F: Ustr = Ustr::from(".f");
+    static ref CST_ASM: Ustr = Ustr::from("asm");
+    static ref CST_EXT_S: Ustr = Ustr::from(".s");
+    static ref CST_ASM2: Ustr = Ustr::from("asm2");
+    static ref CST_EXT_ASM: Ustr = Ustr::from(".asm");
+    static ref CST_ASM_CPP: Ustr = Ustr::from("asm_cpp");
+    static ref CST_EXT_S_CPP: Ustr = Ustr::from(".S");
 }
 
 /// Is this an attribute we want to keep in the project ?
@@ -55,6 +66,35 @@ fn keep_attribute(name: &SimpleName) -> bool {
     )
 }
 
+/// The projects visible while processing a project.  This also records which
+/// of them the project references by name, and the environment variables it
+/// reads.  It is owned by the thread that processes the project, and what it
+/// recorded is moved to the project afterwards.
+pub struct Dependencies<'a> {
+    gprs: &'a [&'a GprFile],
+    referenced: RefCell<HashSet<NodeIndex>>,
+    externals: RefCell<HashMap<Ustr, Option<String>>>,
+}
+
+impl<'a> Dependencies<'a> {
+    pub fn new(gprs: &'a [&'a GprFile]) -> Self {
+        Dependencies {
+            gprs,
+            referenced: Default::default(),
+            externals: Default::default(),
+        }
+    }
+
+    /// The value of an environment variable, for external() and
+    /// external_as_list().  The value is recorded, so that a cached
+    /// evaluation of the project is not reused when it changes.
+    pub fn read_external(&self, name: Ustr) -> Option<String> {
+        let value = std::env::var(name.as_str()).ok();
+        self.externals.borrow_mut().insert(name, value.clone());
+        value
+    }
+}
+
 /// A specific GPR file
 /// Such an object is independent of the scanner that created it, though it
 /// needs an Environment object to resolve paths.
@@ -66,12 +106,15 @@ pub struct GprFile {
     is_aggregate: bool,
     is_abstract: bool,
     path: PathBuf,
-    types: [HashMap<SimpleName, Vec<Ustr>>; PACKAGE_NAME_VARIANTS],
+    types: HashMap<PackageName, HashMap<SimpleName, Vec<Ustr>>>,
 
-    values: [HashMap<
-        SimpleName, // variable or attribute name
-        ExprValue,  // value for each scenario
-    >; PACKAGE_NAME_VARIANTS],
+    values: HashMap<
+        PackageName,
+        HashMap<
+            SimpleName, // variable or attribute name
+            ExprValue,  // value for each scenario
+        >,
+    >,
 
     // List of source directories, after resolving relative paths and /** from
     // the Source_Dirs attribute
@@ -80,6 +123,21 @@ pub struct GprFile {
     // The Naming scheme, and list of source files
     pub naming: PerScenario<Naming>,
     pub sources: PerScenario<Vec<FileInGPR>>,
+
+    // Line of the "with" clause or "extends" declaration for each dependency
+    pub dep_lines: HashMap<PathBuf, u32>,
+
+    // Other projects referenced by name (for their variables, types,
+    // attributes or packages) while processing this project.
+    pub referenced_gprs: HashSet<NodeIndex>,
+
+    // The scenario variables declared in this project: name, valid values
+    // and default, in the order of their declaration.
+    declared_variables: Vec<(Ustr, Vec<Ustr>, Option<Ustr>)>,
+
+    // Environment variables read while processing this project, and their
+    // value.
+    externals: HashMap<Ustr, Option<String>>,
 }
 
 impl GprFile {
@@ -100,58 +158,71 @@ impl GprFile {
         };
 
         // Declare the fallback value for "project'Target" attribute.
-        s.values[PackageName::None as usize].insert(
+        s.values.entry(PackageName::None).or_default().insert(
             SimpleName::Target,
             ExprValue::new_with_str(*CST_X86_64_LINUX),
         );
-        s.values[PackageName::Linker as usize].insert(
+        s.values.entry(PackageName::Linker).or_default().insert(
             SimpleName::LinkerOptions,
             ExprValue::new_with_list(vec![]),
         );
-        s.values[PackageName::None as usize].insert(
+        s.values.entry(PackageName::None).or_default().insert(
             SimpleName::SourceDirs,
             ExprValue::new_with_list(vec![*CST_DOT]),
         );
-        s.values[PackageName::None as usize].insert(
+        s.values.entry(PackageName::None).or_default().insert(
             SimpleName::ObjectDir,
             ExprValue::new_with_list(vec![*CST_DOT]),
         );
-        s.values[PackageName::None as usize].insert(
+        s.values.entry(PackageName::None).or_default().insert(
             SimpleName::ExecDir,
             ExprValue::new_with_list(vec![*CST_DOT]),
         );
-        s.values[PackageName::None as usize].insert(
+        s.values.entry(PackageName::None).or_default().insert(
             SimpleName::Languages,
             ExprValue::new_with_list(vec![*CST_ADA]),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::DotReplacement,
             ExprValue::new_with_str(*CST_MINUS),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::SpecSuffix(*CST_ADA),
             ExprValue::new_with_str(*CST_EXT_ADS),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::BodySuffix(*CST_ADA),
             ExprValue::new_with_str(*CST_EXT_ADB),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::SpecSuffix(*CST_CPP),
             ExprValue::new_with_str(*CST_EXT_HH),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::BodySuffix(*CST_CPP),
             ExprValue::new_with_str(*CST_EXT_CPP),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::SpecSuffix(*CST_C),
             ExprValue::new_with_str(*CST_EXT_H),
         );
-        s.values[PackageName::Naming as usize].insert(
+        s.values.entry(PackageName::Naming).or_default().insert(
             SimpleName::BodySuffix(*CST_C),
             ExprValue::new_with_str(*CST_EXT_C),
         );
+        // As in gprbuild, free-form Fortran sources (".f90") are only found
+        // when the project declares their suffix.
+        for (lang, suffix) in [
+            (*CST_FORTRAN, *CST_EXT_F),
+            (*CST_ASM, *CST_EXT_S),
+            (*CST_ASM2, *CST_EXT_ASM),
+            (*CST_ASM_CPP, *CST_EXT_S_CPP),
+        ] {
+            s.values.entry(PackageName::Naming).or_default().insert(
+                SimpleName::BodySuffix(lang),
+                ExprValue::new_with_str(suffix),
+            );
+        }
         s
     }
 
@@ -159,8 +230,8 @@ impl GprFile {
     /// This is optional, and just a way to reduce the number of combinations
     /// that we will need to look at for scenarios.
     pub fn trim(&mut self) {
-        for pkg in 0..PACKAGE_NAME_VARIANTS {
-            self.values[pkg].retain(|name, _| keep_attribute(name));
+        for pkg in self.values.values_mut() {
+            pkg.retain(|name, _| keep_attribute(name));
         }
     }
 
@@ -188,19 +259,168 @@ impl GprFile {
         }
     }
 
+    /// Retrieve the value of a variable or attribute, only looking in the
+    /// given package of this project.
+    fn get_value(
+        &self,
+        pkg: PackageName,
+        name: &SimpleName,
+    ) -> Option<&ExprValue> {
+        self.values.get(&pkg).and_then(|values| values.get(name))
+    }
+
+    // Retrieve the value of a string attribute
+    pub fn str_attr(
+        &self,
+        pkg: PackageName,
+        name: &SimpleName,
+    ) -> Option<&PerScenario<Ustr>> {
+        match self.get_value(pkg, name) {
+            Some(ExprValue::Str(v)) => Some(v),
+            None => None,
+            v => panic!("Wrong type for attribute {}{}, {:?}", pkg, name, v),
+        }
+    }
+
     // Retrieve the value of a string list attribute
     pub fn strlist_attr(
         &self,
         pkg: PackageName,
         name: &SimpleName,
     ) -> Option<&PerScenario<Vec<Ustr>>> {
-        match self.values[pkg as usize].get(name) {
+        match self.get_value(pkg, name) {
             Some(ExprValue::StrList(v)) => Some(v),
             None => None,
             v => panic!("Wrong type for attribute {}{}, {:?}", pkg, name, v),
         }
     }
 
+    /// The compiler switches for a source file, in the given scenario.  The
+    /// switches might be different in parts of that scenario, so this
+    /// returns the switches for each of those.  Like gprbuild, we only use
+    /// the most specific attribute: Switches for the file itself, then
+    /// Switches for the language, then Default_Switches.
+    /// An attribute only set in some branches of a case statement is an
+    /// empty list in the other branches, so an empty list falls back to the
+    /// next attribute.
+    fn compiler_switches(
+        &self,
+        lang: Ustr,
+        basename: Ustr,
+        scenario: &Scenario,
+        scenars: &AllScenarios,
+    ) -> Vec<(Scenario, Vec<Ustr>)> {
+        let mut result: Vec<(Scenario, Option<&Vec<Ustr>>)> =
+            vec![(scenario.clone(), None)];
+        for name in [
+            SimpleName::Switches(StringOrOthers::Str(basename)),
+            SimpleName::Switches(StringOrOthers::Str(lang)),
+            SimpleName::DefaultSwitches(StringOrOthers::Str(lang)),
+        ] {
+            let Some(switches) =
+                self.strlist_attr(PackageName::Compiler, &name)
+            else {
+                continue;
+            };
+            let mut split = Vec::new();
+            for (context, found) in result {
+                if found.is_some() {
+                    split.push((context, found));
+                    continue;
+                }
+                for (s, list) in switches.iter() {
+                    let s = s & &context;
+                    if !scenars.never_matches(&s) {
+                        split.push((s, (!list.is_empty()).then_some(list)));
+                    }
+                }
+            }
+            result = split;
+        }
+        result
+            .into_iter()
+            .map(|(s, list)| (s, list.cloned().unwrap_or_default()))
+            .collect()
+    }
+
+    /// The directories given with -I in the compiler switches for a source
+    /// file, in the given scenario.  Relative directories are relative to the
+    /// project.
+    pub fn compiler_include_dirs(
+        &self,
+        lang: Ustr,
+        basename: Ustr,
+        scenario: &Scenario,
+        scenars: &AllScenarios,
+        settings: &Settings,
+    ) -> Vec<PathBuf> {
+        let mut result = Vec::new();
+        for (_, list) in
+            self.compiler_switches(lang, basename, scenario, scenars)
+        {
+            let mut iter = list.iter();
+            while let Some(sw) = iter.next() {
+                let dir = match sw.strip_prefix("-I") {
+                    Some("") => iter.next().map(|d| d.as_str()),
+                    dir => dir,
+                };
+                if let Some(dir) =
+                    dir.and_then(|d| self.normalize_path(d, settings))
+                {
+                    if !result.contains(&dir) {
+                        result.push(dir);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// The macros given with -D and -U in the compiler switches for a source
+    /// file, for each part of the given scenario where they differ.  The
+    /// value is None for undefined macros, and defaults to "1" when -D
+    /// doesn't specify one.  When a macro is given several times, the last
+    /// switch wins.
+    pub fn compiler_macros(
+        &self,
+        lang: Ustr,
+        basename: Ustr,
+        scenario: &Scenario,
+        scenars: &AllScenarios,
+    ) -> Vec<Macros> {
+        let mut result = Vec::new();
+        for (_, list) in
+            self.compiler_switches(lang, basename, scenario, scenars)
+        {
+            let mut macros = Macros::new();
+            let mut iter = list.iter();
+            while let Some(sw) = iter.next() {
+                let (def, name) = if let Some(d) = sw.strip_prefix("-D") {
+                    (true, d)
+                } else if let Some(u) = sw.strip_prefix("-U") {
+                    (false, u)
+                } else {
+                    continue;
+                };
+                let name = match name {
+                    "" => match iter.next() {
+                        Some(n) => n.as_str(),
+                        None => break,
+                    },
+                    n => n,
+                };
+                let (name, value) = match (def, name.split_once('=')) {
+                    (true, Some((n, v))) => (n, Some(v)),
+                    (true, None) => (name, Some("1")),
+                    (false, _) => (name, None),
+                };
+                macros.insert(Ustr::from(name), value.map(Ustr::from));
+            }
+            result.push(macros);
+        }
+        result
+    }
+
     //  Resolve source directories from the list of relative path names (as
     //  strings) read from the project file, into full paths.
     //  This is done for all scenarios.
@@ -244,51 +464,131 @@ impl GprFile {
         Ok(())
     }
 
+    /// Read the list of source files from a Source_List_File, relative to
+    /// the project's directory.  Each non-empty line is the basename of a
+    /// source file, and lines starting with "--" are comments.
+    /// An empty name means the attribute is not set in that scenario.
+    fn read_source_list_file(
+        &self,
+        filename: &Ustr,
+    ) -> Result<Option<HashSet<Ustr>>, Error> {
+        if filename.is_empty() {
+            return Ok(None);
+        }
+        let path = self.path.parent().unwrap().join(filename.as_str());
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| Error::IoWithPath(e, path.clone()))?;
+        Ok(Some(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with("--"))
+                .map(Ustr::from)
+                .collect(),
+        ))
+    }
+
     /// Resolve the naming scheme for each scenario
-    pub fn resolve_naming(&mut self, scenars: &mut AllScenarios) {
+    pub fn resolve_naming(
+        &mut self,
+        scenars: &mut AllScenarios,
+    ) -> Result<(), Error> {
         let mut naming =
             self.source_dirs.map(|d| Naming::new_with_dirs(d.clone()));
 
         if let Some(sf) =
             self.strlist_attr(PackageName::None, &SimpleName::SourceFiles)
         {
-            naming.update(sf, Scenario::default(), scenars, |naming, files| {
-                naming.set_source_files(files.iter().cloned().collect())
-            });
+            naming.update(
+                sf,
+                &Scenario::default(),
+                scenars,
+                |naming, files| {
+                    naming.set_source_files(files.iter().cloned().collect())
+                },
+            );
+        }
+
+        if let Some(slf) =
+            self.str_attr(PackageName::None, &SimpleName::SourceListFile)
+        {
+            let lists = slf
+                .try_map(|f| self.read_source_list_file(f))
+                .map_err(|e| Error::WithPath {
+                    path: self.path.clone(),
+                    error: Box::new(e),
+                })?;
+            naming.update(
+                &lists,
+                &Scenario::default(),
+                scenars,
+                |naming, files| {
+                    if let Some(files) = files {
+                        naming.set_source_files(files.clone());
+                    }
+                },
+            );
+        }
+
+        if let Some(sf) = self
+            .strlist_attr(PackageName::None, &SimpleName::ExcludedSourceFiles)
+        {
+            naming.update(
+                sf,
+                &Scenario::default(),
+                scenars,
+                |naming, files| {
+                    naming.set_excluded_source_files(
+                        files.iter().cloned().collect(),
+                    )
+                },
+            );
         }
 
         if let Some(sf) =
             self.strlist_attr(PackageName::None, &SimpleName::Main)
         {
-            naming.update(sf, Scenario::default(), scenars, |naming, files| {
-                naming.main = Some(files.iter().cloned().collect());
-            });
+            naming.update(
+                sf,
+                &Scenario::default(),
+                scenars,
+                |naming, files| {
+                    naming.main = Some(files.iter().cloned().collect());
+                },
+            );
         }
 
         if let Some(sf) =
             self.strlist_attr(PackageName::None, &SimpleName::LibraryInterface)
         {
-            naming.update(sf, Scenario::default(), scenars, |naming, units| {
-                naming.library_interfaces = Some(
-                    units.iter().map(|u| QName::from_str(u, ".")).collect(),
-                );
-            });
+            naming.update(
+                sf,
+                &Scenario::default(),
+                scenars,
+                |naming, units| {
+                    naming.library_interfaces = Some(
+                        units.iter().map(|u| QName::from_str(u, ".")).collect(),
+                    );
+                },
+            );
         }
 
         naming.update(
             self.strlist_attr(PackageName::None, &SimpleName::Languages)
                 .expect("Languages attribute is always defined"),
-            Scenario::default(),
+            &Scenario::default(),
             scenars,
             |naming, langs| naming.set_languages(langs.clone()),
         );
 
-        for (name, val) in &self.values[PackageName::Naming as usize] {
+        for (name, val) in
+            self.values.get(&PackageName::Naming).into_iter().flatten()
+        {
             match (name, val) {
                 (SimpleName::SpecSuffix(lang), ExprValue::Str(v)) => {
                     naming.update(
                         v,
-                        Scenario::default(),
+                        &Scenario::default(),
                         scenars,
                         |naming, suffix| {
                             naming.spec_suffix.insert(*lang, *suffix);
@@ -298,7 +598,7 @@ impl GprFile {
                 (SimpleName::BodySuffix(lang), ExprValue::Str(v)) => {
                     naming.update(
                         v,
-                        Scenario::default(),
+                        &Scenario::default(),
                         scenars,
                         |naming, suffix| {
                             naming.body_suffix.insert(*lang, *suffix);
@@ -308,7 +608,7 @@ impl GprFile {
                 (SimpleName::Spec(unit), ExprValue::Str(v)) => {
                     naming.update(
                         v,
-                        Scenario::default(),
+                        &Scenario::default(),
                         scenars,
                         |naming, filename| {
                             naming.spec_files.insert(*unit, *filename);
@@ -318,7 +618,7 @@ impl GprFile {
                 (SimpleName::Body(unit), ExprValue::Str(v)) => {
                     naming.update(
                         v,
-                        Scenario::default(),
+                        &Scenario::default(),
                         scenars,
                         |naming, filename| {
                             naming.body_files.insert(*unit, *filename);
@@ -328,7 +628,7 @@ impl GprFile {
                 (SimpleName::DotReplacement, ExprValue::Str(v)) => {
                     naming.update(
                         v,
-                        Scenario::default(),
+                        &Scenario::default(),
                         scenars,
                         |naming, repl| {
                             naming.dot_replacement = *repl;
@@ -336,12 +636,14 @@ impl GprFile {
                     );
                 }
                 _ => {
-                    panic!("Unexpected attribute Naming{}", name);
+                    // Other attributes (Casing, Separate_Suffix,...) and
+                    // variables are not needed to find source files.
                 }
             }
         }
 
         self.naming = naming;
+        Ok(())
     }
 
     /// Return the list of source files for all scenarios
@@ -366,6 +668,7 @@ impl GprFile {
         &self,
         scenars: &mut AllScenarios,
         gprs: &GprMap,
+        files: &SourceFilesMap,
         settings: &Settings,
     ) {
         if !self.is_library || !self.is_aggregate {
@@ -387,7 +690,7 @@ impl GprFile {
                     });
                 prj_intf.update(
                     prj,
-                    Scenario::default(),
+                    &Scenario::default(),
                     scenars,
                     |prj_int, p| {
                         prj_int.1.extend(
@@ -405,7 +708,8 @@ impl GprFile {
                         for (s2, sources) in gpr.sources.iter() {
                             if s & s2 != Scenario::empty() {
                                 for source in sources.iter() {
-                                    let mut sm = source.file.borrow_mut();
+                                    let mut sm =
+                                        files[&source.path].borrow_mut();
                                     sm.is_library_interface =
                                         prj_int.0.contains(&sm.unitname);
                                 }
@@ -424,13 +728,15 @@ impl GprFile {
         name: SimpleName,
         mut valid: Vec<Ustr>,
     ) -> Result<(), Error> {
-        let old = self.types[package as usize].get(&name);
-        if old.is_none() {
-            valid.sort();
-            self.types[package as usize].insert(name, valid);
-            Ok(())
-        } else {
-            Err(Error::AlreadyDeclared)?
+        match self.types.entry(package).or_default().entry(name) {
+            std::collections::hash_map::Entry::Vacant(e) => {
+                valid.sort();
+                e.insert(valid);
+                Ok(())
+            }
+            std::collections::hash_map::Entry::Occupied(_) => {
+                Err(Error::AlreadyDeclared)?
+            }
         }
     }
 
@@ -446,17 +752,18 @@ impl GprFile {
         &mut self,
         package: PackageName,
         name: SimpleName,
-        context: Scenario,
+        context: &Scenario,
         scenars: &mut AllScenarios,
         mut delta: ExprValue,
     ) -> Result<(), Error> {
-        let old = self.values[package as usize].get(&name);
-        if old.is_none() {
-            self.values[package as usize].insert(name, delta);
-            return Ok(());
-        }
-
-        let mut old = old.unwrap().clone();
+        let values = self.values.entry(package).or_default();
+        let mut old = match values.get(&name) {
+            None => {
+                values.insert(name, delta);
+                return Ok(());
+            }
+            Some(old) => old.clone(),
+        };
 
         match (&mut old, &mut delta) {
             (ExprValue::Str(ov), ExprValue::Str(d)) => {
@@ -473,7 +780,7 @@ impl GprFile {
             }
         }
 
-        self.values[package as usize].insert(name, old);
+        values.insert(name, old);
         Ok(())
     }
 
@@ -482,16 +789,21 @@ impl GprFile {
     fn lookup_gpr<'a>(
         &'a self,
         name: &QualifiedName,
-        dependencies: &'a [&GprFile],
+        dependencies: &Dependencies<'a>,
     ) -> Result<&'a GprFile, Error> {
         match &name.project {
             None => Ok(self),
             Some(c) if *c == self.name => Ok(self),
-            Some(n) => dependencies
-                .iter()
-                .copied()
-                .find(|gpr| gpr.name == *n)
-                .ok_or_else(|| Error::not_found(name)),
+            Some(n) => {
+                let gpr = dependencies
+                    .gprs
+                    .iter()
+                    .copied()
+                    .find(|gpr| gpr.name == *n)
+                    .ok_or_else(|| Error::not_found(name))?;
+                dependencies.referenced.borrow_mut().insert(gpr.node);
+                Ok(gpr)
+            }
         }
     }
 
@@ -499,7 +811,7 @@ impl GprFile {
     pub fn lookup_type<'a>(
         &'a self,
         name: &QualifiedName,
-        dependencies: &'a [&GprFile],
+        dependencies: &Dependencies<'a>,
         current_pkg: PackageName,
     ) -> Result<&'a Vec<Ustr>, Error> {
         let project = self.lookup_gpr(name, dependencies)?;
@@ -508,11 +820,17 @@ impl GprFile {
         // An unqualified name is first searched in the current package
         if name.package == PackageName::None && current_pkg != PackageName::None
         {
-            r1 = project.types[current_pkg as usize].get(&name.name);
+            r1 = project
+                .types
+                .get(&current_pkg)
+                .and_then(|types| types.get(&name.name));
         }
 
         if r1.is_none() {
-            r1 = project.types[name.package as usize].get(&name.name);
+            r1 = project
+                .types
+                .get(&name.package)
+                .and_then(|types| types.get(&name.name));
         }
 
         r1.ok_or_else(|| Error::not_found(name))
@@ -524,7 +842,7 @@ impl GprFile {
     pub fn lookup<'a>(
         &'a self,
         name: &QualifiedName,
-        dependencies: &'a [&GprFile],
+        dependencies: &Dependencies<'a>,
         current_pkg: PackageName,
     ) -> Result<&'a ExprValue, Error> {
         let project = self.lookup_gpr(name, dependencies)?;
@@ -533,11 +851,11 @@ impl GprFile {
         // An unqualified name is first searched in the current package
         if name.package == PackageName::None && current_pkg != PackageName::None
         {
-            r1 = project.values[current_pkg as usize].get(&name.name);
+            r1 = project.get_value(current_pkg, &name.name);
         }
 
         if r1.is_none() {
-            r1 = project.values[name.package as usize].get(&name.name);
+            r1 = project.get_value(name.package, &name.name);
         }
 
         r1.ok_or_else(|| Error::not_found(name))
@@ -546,9 +864,9 @@ impl GprFile {
     /// Process one statement
     fn process_one_stmt(
         &mut self,
-        dependencies: &[&GprFile],
+        dependencies: &Dependencies,
         scenarios: &mut AllScenarios,
-        context: Scenario,
+        context: &Scenario,
         current_pkg: PackageName,
         statement: &Statement,
     ) -> std::result::Result<(), Error> {
@@ -571,21 +889,24 @@ impl GprFile {
                 // not check its actual value from the environment or the
                 // default, but instead create a ExprValue with a different
                 // value for each scenario
-                let ext = expr.has_external();
+                let ext = match typename {
+                    Some(_) => expr.has_external()?,
+                    None => None,
+                };
                 let expr = match (typename, ext) {
                     (Some(typename), Some(ext)) => {
-                        let valid = self.lookup_type(
-                            typename,
-                            dependencies,
-                            current_pkg,
-                        )?;
+                        let valid = self
+                            .lookup_type(typename, dependencies, current_pkg)?
+                            .to_vec();
 
                         // Check that this variable wasn't already declared
                         // with a different set of values.
-                        scenarios
-                            .try_add_variable(ext.0, valid, ext.1)?
+                        let value = scenarios
+                            .try_add_variable(ext.0, &valid, ext.1)?
                             .value()
-                            .clone()
+                            .clone();
+                        self.declared_variables.push((ext.0, valid, ext.1));
+                        value
                     }
                     _ => {
                         // Else we have a standard variable (either untyped
@@ -637,13 +958,13 @@ impl GprFile {
             } => {
                 match (renames, extends) {
                     (Some(r), None) | (None, Some(r)) => {
-                        let mut orig = self.lookup_gpr(r, dependencies)?.values
-                            [*name as usize]
-                            .clone();
-                        for (n, expr) in orig.drain() {
-                            self.values[*name as usize]
-                                .insert(n.clone(), expr.clone());
-                        }
+                        let orig = self
+                            .lookup_gpr(r, dependencies)?
+                            .values
+                            .get(name)
+                            .cloned()
+                            .unwrap_or_default();
+                        self.values.entry(*name).or_default().extend(orig);
                     }
                     _ => {}
                 }
@@ -686,7 +1007,7 @@ impl GprFile {
                         self.process_body(
                             dependencies,
                             scenarios,
-                            scenar,
+                            &scenar,
                             current_pkg,
                             &w.body,
                         )?;
@@ -700,9 +1021,9 @@ impl GprFile {
     /// Process a set of statements
     fn process_body(
         &mut self,
-        dependencies: &[&GprFile],
+        dependencies: &Dependencies,
         scenarios: &mut AllScenarios,
-        context: Scenario,
+        context: &Scenario,
         current_pkg: PackageName,
         body: &StatementList,
     ) -> std::result::Result<(), Error> {
@@ -718,6 +1039,116 @@ impl GprFile {
         Ok(())
     }
 
+    /// The result of processing the project, to store in the cache.  The
+    /// projects referenced by name are given by the caller, as paths.
+    pub fn cached(
+        &self,
+        scenarios: &AllScenarios,
+        referenced: Vec<PathBuf>,
+    ) -> CachedGpr {
+        CachedGpr {
+            variables: self
+                .declared_variables
+                .iter()
+                .map(|(name, valid, default)| {
+                    (
+                        name.to_string(),
+                        ustrs_to_strings(valid),
+                        default.map(|d| d.to_string()),
+                    )
+                })
+                .collect(),
+            types: self
+                .types
+                .iter()
+                .flat_map(|(pkg, types)| {
+                    types
+                        .iter()
+                        .map(|(n, v)| (*pkg, n.clone(), ustrs_to_strings(v)))
+                })
+                .collect(),
+            values: self
+                .values
+                .iter()
+                .flat_map(|(pkg, values)| {
+                    values.iter().map(|(n, v)| {
+                        (*pkg, n.clone(), CachedValue::new(v, scenarios))
+                    })
+                })
+                .collect(),
+            referenced,
+            externals: self
+                .externals
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.clone()))
+                .collect(),
+        }
+    }
+
+    /// Move the project to another set of scenario variables.  The project
+    /// was processed with `local`, a copy of `scenarios` that might have
+    /// allocated different bits for the variables it declared.  These
+    /// variables are declared again in `scenarios`, and the values of the
+    /// project are converted to the new bits if needed.
+    pub fn merge_scenarios(
+        &mut self,
+        local: &AllScenarios,
+        scenarios: &mut AllScenarios,
+    ) -> std::result::Result<(), Error> {
+        for (name, valid, default) in &self.declared_variables {
+            scenarios.try_add_variable(*name, valid, *default)?;
+        }
+        if let Some(remap) = scenarios.remap_from(local) {
+            for pkg in self.values.values_mut() {
+                for value in pkg.values_mut() {
+                    value.remap_scenarios(&remap);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore the result of processing the project in a previous run,
+    /// instead of calling process().  The scenario variables declared by the
+    /// project are declared again.
+    pub fn restore(
+        &mut self,
+        raw: &RawGPR,
+        cached: &CachedGpr,
+        scenarios: &mut AllScenarios,
+    ) -> std::result::Result<(), Error> {
+        self.name = raw.name;
+        self.dep_lines.clone_from(&raw.dep_lines);
+        self.declared_variables.clear();
+        for (name, valid, default) in &cached.variables {
+            let name = Ustr::from(name);
+            let valid = strings_to_ustrs(valid);
+            let default = default.as_deref().map(Ustr::from);
+            scenarios.try_add_variable(name, &valid, default)?;
+            self.declared_variables.push((name, valid, default));
+        }
+        self.types.clear();
+        for (pkg, name, valid) in &cached.types {
+            self.types
+                .entry(*pkg)
+                .or_default()
+                .insert(name.clone(), strings_to_ustrs(valid));
+        }
+        self.values.clear();
+        for (pkg, name, value) in &cached.values {
+            self.values
+                .entry(*pkg)
+                .or_default()
+                .insert(name.clone(), value.value(scenarios)?);
+        }
+        self.externals = cached
+            .externals
+            .iter()
+            .map(|(n, v)| (Ustr::from(n), v.clone()))
+            .collect();
+        Ok(())
+    }
+
     /// Process the raw gpr file into the final list of attributes
     pub fn process(
         &mut self,
@@ -727,30 +1158,34 @@ impl GprFile {
         scenarios: &mut AllScenarios,
     ) -> std::result::Result<(), Error> {
         self.name = raw.name;
+        self.dep_lines.clone_from(&raw.dep_lines);
 
         if let Some(ext) = extends {
-            for v in 0..PACKAGE_NAME_VARIANTS {
-                self.values[v].clone_from(&ext.values[v]);
-            }
+            self.values.clone_from(&ext.values);
         }
 
-        self.process_body(
-            dependencies,
-            scenarios,
-            Scenario::default(),
-            PackageName::None,
-            &raw.body,
-        )
-        .map_err(|e| Error::WithPath {
-            path: self.path.clone(),
-            error: Box::new(e),
-        })
+        let dependencies = Dependencies::new(dependencies);
+        let result = self
+            .process_body(
+                &dependencies,
+                scenarios,
+                &Scenario::default(),
+                PackageName::None,
+                &raw.body,
+            )
+            .map_err(|e| Error::WithPath {
+                path: self.path.clone(),
+                error: Box::new(e),
+            });
+        self.referenced_gprs = dependencies.referenced.into_inner();
+        self.externals = dependencies.externals.into_inner();
+        result
     }
 
     /// Find all scenarios that result in different values in the project
     pub fn find_used_scenarios(&self, scenars: &mut HashSet<Scenario>) {
-        for pkg in 0..PACKAGE_NAME_VARIANTS {
-            for v in self.values[pkg].values() {
+        for pkg in self.values.values() {
+            for v in pkg.values() {
                 v.find_used_scenarios(scenars);
             }
         }
@@ -761,12 +1196,10 @@ impl GprFile {
         println!("file: {}", self.path.display());
         println!("project {} is", self.name);
 
-        for pkgidx in 0..PACKAGE_NAME_VARIANTS {
-            if self.values[pkgidx].is_empty() {
-                continue;
-            }
-            let pkg: PackageName = unsafe { std::mem::transmute(pkgidx) };
-            for (attrname, value) in &self.values[pkgidx] {
+        let mut pkgs = self.values.keys().collect::<Vec<_>>();
+        pkgs.sort();
+        for pkg in pkgs {
+            for (attrname, value) in &self.values[pkg] {
                 if print_vars || !matches!(attrname, SimpleName::Name(_)) {
                     println!(
                         "   for {}{}\n{}",
@@ -799,16 +1232,18 @@ pub mod tests {
     use crate::{
         ada_lexer::{AdaLexer, AdaLexerOptions},
         allscenarios::AllScenarios,
+        environment::tests::TempDir,
         errors::Error,
         gpr::GprFile,
         gpr_scanner::GprScanner,
         graph::NodeIndex,
         packagename::PackageName,
         rawgpr::RawGPR,
+        scenarios::Scenario,
         settings::Settings,
         simplename::SimpleName,
     };
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use ustr::Ustr;
 
     /// Parse a project, for a test
@@ -847,8 +1282,7 @@ pub mod tests {
         scenarios: &AllScenarios,
         expected: &str,
     ) {
-        let v =
-            gpr.values[pkg as usize].get(&SimpleName::Name(Ustr::from(name)));
+        let v = gpr.get_value(pkg, &SimpleName::Name(Ustr::from(name)));
         let actual = match v {
             None => "NONE".to_string(),
             Some(a) => a.format(scenarios, "", "\n"),
@@ -884,4 +1318,159 @@ pub mod tests {
         gpr.print_details(&scenarios, true);
         Ok(())
     }
+
+    #[test]
+    fn any_package() -> Result<(), Error> {
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               package Install is
+                  V := "install";
+                  for Switches ("Ada") use ("-v");
+               end Install;
+               package Pretty_Printer is
+                  V := "pp";
+               end Pretty_Printer;
+               package My_Tool is
+                  V := Pretty_Printer.V & "-tool";
+                  for Switches ("Ada") use ("-g");
+               end My_Tool;
+               package Other_Tool is
+                  V := My_Tool.V & "-other";
+                  for Switches ("Ada") use My_Tool'Switches ("Ada");
+               end Other_Tool;
+               end P;"#,
+        )?;
+        let mut scenarios = crate::allscenarios::AllScenarios::default();
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios)?;
+        crate::gpr::tests::assert_variable(
+            &gpr,
+            PackageName::Install,
+            "v",
+            &scenarios,
+            "* install",
+        );
+        crate::gpr::tests::assert_variable(
+            &gpr,
+            PackageName::Other(Ustr::from("my_tool")),
+            "v",
+            &scenarios,
+            "* pp-tool",
+        );
+
+        // A user-defined package declared earlier in the same project
+        crate::gpr::tests::assert_variable(
+            &gpr,
+            PackageName::Other(Ustr::from("other_tool")),
+            "v",
+            &scenarios,
+            "* pp-tool-other",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compiler_switches() -> Result<(), Error> {
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               type T is ("debug", "release");
+               Mode : T := external ("mode");
+               package Compiler is
+                  for Default_Switches ("C") use ("-DA", "-DB=2");
+                  case Mode is
+                     when "debug" =>
+                        for Switches ("main.c") use ("-UA", "-I", "inc");
+                     when "release" => null;
+                  end case;
+               end Compiler;
+               end P;"#,
+        )?;
+        let mut scenarios = crate::allscenarios::AllScenarios::default();
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios)?;
+        let macros = |file: &str| {
+            let mut result: Vec<String> = gpr
+                .compiler_macros(
+                    Ustr::from("c"),
+                    Ustr::from(file),
+                    &Scenario::default(),
+                    &scenarios,
+                )
+                .iter()
+                .map(|m| {
+                    let mut m: Vec<String> = m
+                        .iter()
+                        .map(|(k, v)| match v {
+                            Some(v) => format!("{}={}", k, v),
+                            None => format!("!{}", k),
+                        })
+                        .collect();
+                    m.sort();
+                    m.join(",")
+                })
+                .collect();
+            result.sort();
+            result
+        };
+
+        // Switches for the file replace Default_Switches, rather than
+        // adding to them, except in the scenario where they are not set.
+        assert_eq!(macros("main.c"), vec!["!A", "A=1,B=2"]);
+        assert_eq!(macros("other.c"), vec!["A=1,B=2"]);
+        assert_eq!(
+            gpr.compiler_include_dirs(
+                Ustr::from("c"),
+                Ustr::from("main.c"),
+                &Scenario::default(),
+                &scenarios,
+                &Settings::default(),
+            ),
+            vec![PathBuf::from("inc")],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn excluded_source_files() -> Result<(), Error> {
+        // The list is found relative to the project's directory
+        let dir = TempDir::new("source_list")?;
+        dir.write(&[(
+            "lists/sources.txt",
+            "-- comment\na.adb\n\n  b.adb\nc.adb\n",
+        )])?;
+
+        let mut raw = crate::gpr::tests::parse(
+            r#"project P is
+               type T is ("a", "b");
+               E1 : T := external ("e1");
+               for Source_List_File use "lists/sources.txt";
+               case E1 is
+                  when "a" => for Excluded_Source_Files use ("b.adb");
+                  when "b" => null;
+               end case;
+               end P;"#,
+        )?;
+        raw.path = dir.0.join("p.gpr");
+        let mut scenarios = crate::allscenarios::AllScenarios::default();
+        let mut gpr = crate::gpr::tests::process(&raw, &mut scenarios)?;
+        gpr.resolve_naming(&mut scenarios)?;
+
+        let mut valid = gpr
+            .naming
+            .iter()
+            .map(|(s, naming)| {
+                format!(
+                    "{} {}",
+                    scenarios.describe(s),
+                    ["a.adb", "b.adb", "c.adb", "d.adb"]
+                        .iter()
+                        .filter(|f| naming.is_source(&Ustr::from(f)))
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )
+            })
+            .collect::<Vec<_>>();
+        valid.sort();
+        assert_eq!(valid, vec!["e1=a a.adb,c.adb", "e1=b a.adb,b.adb,c.adb"]);
+        Ok(())
+    }
 }
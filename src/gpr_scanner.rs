@@ -21,7 +21,8 @@ pub type GprPathToIndex = HashMap<std::path::PathBuf, NodeIndex>;
 pub struct GprScanner<'a> {
     base: BaseScanner<AdaLexer<'a>>,
     gpr: RawGPR,
-    current_pkg: PackageName, //  What are we parsing
+    current_pkg: PackageName,   //  What are we parsing
+    packages: Vec<PackageName>, //  Packages declared so far
     settings: &'a Settings,
 }
 
@@ -36,13 +37,16 @@ impl<'a> GprScanner<'a> {
             base: BaseScanner::new(lex),
             gpr: RawGPR::new(path),
             current_pkg: PackageName::None,
+            packages: Vec::new(),
             settings,
         };
 
         loop {
             match scan.base.peek() {
                 TokenKind::EndOfFile => break,
-                TokenKind::With => scan.parse_with_clause(),
+                TokenKind::With | TokenKind::Limited => {
+                    scan.parse_with_clause()
+                }
                 _ => scan.parse_project_declaration(),
             }
             .map_err(|e| scan.base.error_with_location(e))?;
@@ -104,7 +108,7 @@ impl<'a> GprScanner<'a> {
                         let name3 = self.base.expect_identifier()?;
                         Ok(QualifiedName {
                             project: name1,
-                            package: PackageName::new(name2)?,
+                            package: PackageName::new(name2),
                             name: SimpleName::new_var(name3),
                         })
                     }
@@ -112,20 +116,21 @@ impl<'a> GprScanner<'a> {
                         let _ = self.base.next_token(); //  consume the tick
                         Ok(QualifiedName {
                             project: name1,
-                            package: PackageName::new(name2)?,
+                            package: PackageName::new(name2),
                             name: self.expect_unqualified_attrname()?,
                         })
                     }
                     _ => Ok(QualifiedName::from_two(
                         name1,
                         SimpleName::new_var(name2),
+                        &self.packages,
                     )),
                 }
             }
             TokenKind::Tick => {
                 let _ = self.base.next_token(); //  consume the dot
                 let attrname = self.expect_unqualified_attrname()?;
-                Ok(QualifiedName::from_two(name1, attrname))
+                Ok(QualifiedName::from_two(name1, attrname, &self.packages))
             }
             _ => match name1 {
                 None => Err(Error::MissingAttributeNameAfterProject)?,
@@ -139,24 +144,53 @@ impl<'a> GprScanner<'a> {
     }
 
     /// Resolve relative paths for project dependencies.
+    /// As gprbuild does, we first look in the directory of the importing
+    /// project, then in each directory of the project path.
     /// Optionally resolves symbolic links.
-    fn normalize_gpr_path(&self, path: &str) -> Result<PathBuf, Error> {
-        let mut p = self.gpr.path.parent().unwrap().join(path);
-        p.set_extension("gpr");
-        if self.settings.resolve_symbolic_links {
-            std::fs::canonicalize(&p).map_err(|e| Error::IoWithPath(e, p))
-        } else {
-            Ok(p.clean())
+    fn normalize_gpr_path(&self, path: Ustr) -> Result<PathBuf, Error> {
+        let mut tried = Vec::new();
+        for dir in std::iter::once(self.gpr.path.parent().unwrap())
+            .chain(self.settings.project_path.iter().map(PathBuf::as_path))
+        {
+            let mut p = dir.join(path.as_str());
+            p.set_extension("gpr");
+            if p.is_file() {
+                return if self.settings.resolve_symbolic_links {
+                    std::fs::canonicalize(&p)
+                        .map_err(|e| Error::IoWithPath(e, p))
+                } else {
+                    Ok(p.clean())
+                };
+            }
+            tried.push(dir.to_path_buf());
         }
+        Err(Error::ProjectNotFound(path, tried))
     }
 
-    /// Expect a with_clause
+    /// Expect a with_clause, possibly a limited one and importing several
+    /// projects at once.
     fn parse_with_clause(&mut self) -> Result<(), Error> {
+        let limited = self.base.peek() == TokenKind::Limited;
+        if limited {
+            let _ = self.base.next_token(); //  consume "limited"
+        }
         self.base.expect(TokenKind::With)?;
 
-        let path = self.base.expect_str()?;
-        let normalized = self.normalize_gpr_path(path.as_str())?;
-        self.gpr.imported.push(normalized);
+        loop {
+            let line = self.base.peek_line();
+            let path = self.base.expect_str()?;
+            let normalized = self.normalize_gpr_path(path)?;
+            self.gpr.dep_lines.insert(normalized.clone(), line);
+            if limited {
+                self.gpr.limited_imported.push(normalized);
+            } else {
+                self.gpr.imported.push(normalized);
+            }
+            if self.base.peek() != TokenKind::Comma {
+                break;
+            }
+            let _ = self.base.next_token(); //  consume the comma
+        }
         self.base.expect(TokenKind::Semicolon)?;
         Ok(())
     }
@@ -179,8 +213,10 @@ impl<'a> GprScanner<'a> {
 
         self.gpr.name = self.base.expect_identifier()?;
         self.gpr.extends = if self.base.peek() == TokenKind::Extends {
+            let line = self.base.peek_line();
             let ext = self.parse_project_extension()?;
-            let normalized = self.normalize_gpr_path(ext.as_str())?;
+            let normalized = self.normalize_gpr_path(ext)?;
+            self.gpr.dep_lines.insert(normalized.clone(), line);
             Some(normalized)
         } else {
             None
@@ -231,6 +267,12 @@ impl<'a> GprScanner<'a> {
 
     fn parse_project_extension(&mut self) -> Result<Ustr, Error> {
         self.base.expect(TokenKind::Extends)?;
+        if let TokenKind::Identifier(s) = self.base.peek() {
+            if s.as_str() == "all" {
+                let _ = self.base.next_token(); //  consume "all"
+                self.gpr.extends_all = true;
+            }
+        }
         self.base.expect_str()
     }
 
@@ -247,12 +289,13 @@ impl<'a> GprScanner<'a> {
 
     fn parse_package_declaration(&mut self) -> Result<Statement, Error> {
         let startname = self.base.expect_identifier()?;
-        let name = PackageName::new(startname)?;
+        let name = PackageName::new(startname);
         let mut extends: Option<QualifiedName> = None;
         let mut renames: Option<QualifiedName> = None;
         let mut body = Vec::new();
 
         self.current_pkg = name;
+        self.packages.push(name);
 
         loop {
             match self.base.next_token() {
@@ -442,12 +485,15 @@ impl<'a> GprScanner<'a> {
                     let _ = self.base.next_token();
                     result.push(RawExpr::Others);
                 }
-                TokenKind::String(_) | TokenKind::Identifier(_) => {
+                TokenKind::String(_)
+                | TokenKind::Identifier(_)
+                | TokenKind::Project
+                | TokenKind::OpenParenthesis => {
                     result.push(self.parse_expression()?);
                 }
                 _ => {
                     let n = self.base.safe_next()?;
-                    Err(Error::wrong_token("others|string", n))?;
+                    Err(Error::wrong_token("others|string|(", n))?;
                 }
             };
 
@@ -567,13 +613,18 @@ impl<'a> GprScanner<'a> {
 
 #[cfg(test)]
 mod tests {
+    use crate::ada_lexer::{AdaLexer, AdaLexerOptions};
+    use crate::environment::tests::TempDir;
     use crate::errors::Error;
+    use crate::gpr_scanner::GprScanner;
     use crate::packagename::PackageName;
     use crate::qualifiedname::QualifiedName;
     use crate::rawexpr::tests::build_expr_list;
     use crate::rawexpr::{RawExpr, Statement, StatementList};
     use crate::rawgpr::RawGPR;
+    use crate::settings::Settings;
     use crate::simplename::{SimpleName, StringOrOthers};
+    use std::path::{Path, PathBuf};
     use ustr::Ustr;
 
     fn do_check<F>(s: &str, check: F)
@@ -684,6 +735,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_unknown_attribute() {
+        expect_statements(
+            "project A is
+                for Roots (\"Main.adb\") use (\"Pkg\");
+                package Naming is
+                   for Implementation_Suffix (\"Ada\") use \".b\";
+                end Naming;
+                package Compiler is
+                   for Driver (\"C\") use \"gcc\";
+                end Compiler;
+             end A;",
+            vec![
+                (
+                    2,
+                    Statement::AttributeDecl {
+                        name: SimpleName::Attribute {
+                            name: Ustr::from("roots"),
+                            index: Some(StringOrOthers::Str(Ustr::from(
+                                "Main.adb",
+                            ))),
+                            case_insensitive: false,
+                        },
+                        value: build_expr_list(&["Pkg"]),
+                    },
+                ),
+                (
+                    3,
+                    Statement::Package {
+                        name: PackageName::Naming,
+                        renames: None,
+                        extends: None,
+                        body: vec![(
+                            4,
+                            Statement::AttributeDecl {
+                                name: SimpleName::BodySuffix(Ustr::from("ada")),
+                                value: RawExpr::Str(Ustr::from(".b")),
+                            },
+                        )],
+                    },
+                ),
+                (
+                    6,
+                    Statement::Package {
+                        name: PackageName::Compiler,
+                        renames: None,
+                        extends: None,
+                        body: vec![(
+                            7,
+                            Statement::AttributeDecl {
+                                name: SimpleName::Attribute {
+                                    name: Ustr::from("driver"),
+                                    index: Some(StringOrOthers::Str(
+                                        Ustr::from("c"),
+                                    )),
+                                    case_insensitive: true,
+                                },
+                                value: RawExpr::Str(Ustr::from("gcc")),
+                            },
+                        )],
+                    },
+                ),
+            ],
+        );
+
+        expect_error(
+            "project A is
+                for Main (\"a\") use (\"a.adb\");
+             end A;",
+            ":memory::3 Invalid attribute name main(a)",
+        );
+    }
+
     #[test]
     fn parse_external() {
         expect_statements(
@@ -722,5 +846,82 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_with_project_path() -> Result<(), Error> {
+        let tmp = TempDir::new("project_path")?;
+        tmp.write(&[("lib.gpr", "project Lib is end Lib;")])?;
+        let dir = &tmp.0;
+        let settings = Settings {
+            project_path: vec![PathBuf::from("/nonexistent"), dir.clone()],
+            ..Default::default()
+        };
+        let parse = |s: &str| {
+            let mut file = crate::files::File::new_from_str(s);
+            let options = AdaLexerOptions {
+                kw_aggregate: true,
+                kw_body: false,
+            };
+            GprScanner::parse(
+                AdaLexer::new(&mut file, options)?,
+                Path::new("/memory/a.gpr"),
+                &settings,
+            )
+        };
+
+        let g = parse("with \"lib\"; project A is end A;")?;
+        assert_eq!(g.imported, vec![dir.join("lib.gpr")]);
+
+        match parse("with \"missing.gpr\"; project A is end A;") {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                format!(
+                    ":memory::1 Project missing.gpr not found in /memory, \
+                     /nonexistent, {}",
+                    dir.display()
+                )
+            ),
+            Ok(_) => panic!("missing.gpr should not be found"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_limited_with_and_extends_all() -> Result<(), Error> {
+        let tmp = TempDir::new("limited_with")?;
+        tmp.write(&[
+            ("a.gpr", "project A is end A;"),
+            ("b.gpr", "project B is end B;"),
+            ("c.gpr", "project C is end C;"),
+            ("d.gpr", "project D is end D;"),
+        ])?;
+        let dir = &tmp.0;
+        let settings = Settings {
+            project_path: vec![dir.clone()],
+            ..Default::default()
+        };
+        let mut file = crate::files::File::new_from_str(
+            "limited with \"a\", \"b\";
+             with \"c\";
+             project E extends all \"d\" is end E;",
+        );
+        let options = AdaLexerOptions {
+            kw_aggregate: true,
+            kw_body: false,
+        };
+        let g = GprScanner::parse(
+            AdaLexer::new(&mut file, options)?,
+            Path::new("/memory/e.gpr"),
+            &settings,
+        )?;
+        assert_eq!(
+            g.limited_imported,
+            vec![dir.join("a.gpr"), dir.join("b.gpr")]
+        );
+        assert_eq!(g.imported, vec![dir.join("c.gpr")]);
+        assert_eq!(g.extends, Some(dir.join("d.gpr")));
+        assert!(g.extends_all);
+        Ok(())
+    }
+
     //    ... tests extends
 }
@@ -1,12 +1,18 @@
 use crate::{
-    errors::Error, qnames::QName, scenarios::Scenario, sourcefile::SourceKind,
+    allscenarios::AllScenarios,
+    errors::Error,
+    qnames::QName,
+    scenarios::Scenario,
+    sourcefile::{ImportKind, SourceKind},
 };
 use petgraph::{
-    algo::toposort,
-    graph::Graph,
-    visit::{Bfs, EdgeRef},
+    algo::{tarjan_scc, toposort},
+    graph::{EdgeReference, Graph},
+    visit::{Bfs, EdgeFiltered, EdgeRef, IntoNeighbors},
     Directed, Direction,
 };
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem::{discriminant, Discriminant};
 use std::path::PathBuf;
 
 pub type NodeIndex = petgraph::graph::NodeIndex<u32>;
@@ -15,8 +21,6 @@ pub type NodeIndex = petgraph::graph::NodeIndex<u32>;
 #[derive(Debug)]
 pub enum Node {
     Project(PathBuf),
-
-    #[allow(dead_code)]
     Unit(QName),
     Source(PathBuf),
 }
@@ -29,20 +33,22 @@ pub enum Node {
 ///    a rare case in practice.
 ///  - A given source file could in theory be part of two different projects,
 ///    though for two non-overlapping scenarios.
-///  - Source files import zero or more units (in C, we import a source file
-///    directly, but then a unit is the same as a source file in this case).
+///  - Source files import zero or more units.  In C, we import a source file
+///    directly: an include is an edge to the header file itself, and that
+///    header stands for the unit it belongs to.
 ///  - A source file depends on the parent unit implicitly.
 ///  - An implementation or a separate source file depend on all files from the
 ///    same unit.  A Spec however doesn't (so that modifying the body doesn't
 ///    require recompiling the spec for instance).
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub enum Edge {
     GPRExtends,                         // between for project files
     GPRImports,                         // between project files
+    GPRLimitedImports,                  // "limited with" between projects
     ProjectSource(Scenario),            // from project to owned source file
     UnitSource((SourceKind, Scenario)), // from unit to owned source files
-    SourceImports,                      // from source file to imported unit
+    SourceImports(ImportKind),          // from source file to unit or header
 }
 
 type G = Graph<Node, Edge, Directed, u32>;
@@ -57,6 +63,62 @@ impl DepGraph {
         }
     }
 
+    pub fn get_source(&self, idx: NodeIndex) -> Result<&PathBuf, Error> {
+        match &self.0[idx] {
+            Node::Source(s) => Ok(s),
+            u => Err(Error::InvalidGraphNode(format!("{:?}", u))),
+        }
+    }
+
+    /// The projects that own a source file, in any scenario
+    pub fn projects_of_source(&self, source: NodeIndex) -> Vec<NodeIndex> {
+        let mut result: Vec<NodeIndex> = self
+            .0
+            .edges_directed(source, Direction::Incoming)
+            .filter(|e| matches!(e.weight(), Edge::ProjectSource(_)))
+            .map(|e| e.source())
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// The projects that own one of the source files of a unit
+    pub fn projects_of_unit(&self, unit: NodeIndex) -> Vec<NodeIndex> {
+        let mut result: Vec<NodeIndex> = self
+            .0
+            .edges_directed(unit, Direction::Outgoing)
+            .filter(|e| matches!(e.weight(), Edge::UnitSource(_)))
+            .flat_map(|e| self.projects_of_source(e.target()))
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// The units imported through a `SourceImports` edge to target.  This is
+    /// target itself, unless it is a header file, in which case this is the
+    /// units the header belongs to.
+    pub fn imported_units(&self, target: NodeIndex) -> Vec<NodeIndex> {
+        match self.0[target] {
+            Node::Source(_) => self
+                .0
+                .edges_directed(target, Direction::Incoming)
+                .filter(|e| matches!(e.weight(), Edge::UnitSource(_)))
+                .map(|e| e.source())
+                .collect(),
+            _ => vec![target],
+        }
+    }
+
+    /// The projects that own the target of a `SourceImports` edge
+    pub fn projects_of_import(&self, target: NodeIndex) -> Vec<NodeIndex> {
+        match self.0[target] {
+            Node::Source(_) => self.projects_of_source(target),
+            _ => self.projects_of_unit(target),
+        }
+    }
+
     pub fn node_count(&self) -> usize {
         self.0.node_count()
     }
@@ -73,20 +135,144 @@ impl DepGraph {
         self.0.add_edge(from, to, data);
     }
 
+    /// Collapse parallel edges of the same kind (and the same source kind for
+    /// units).  Their scenarios are merged when the union can be represented
+    /// as a single scenario, so we might still have multiple edges between
+    /// two nodes afterwards.
+    /// Returns the number of edges that were removed.
+    pub fn merge_parallel_edges(&mut self, scenars: &AllScenarios) -> usize {
+        type Key =
+            (NodeIndex, NodeIndex, Discriminant<Edge>, Option<SourceKind>);
+        let before = self.0.edge_count();
+
+        // Group edges, preserving their order
+        let mut groups: Vec<(Key, Edge, Vec<Scenario>)> = Vec::new();
+        let mut group_index: HashMap<Key, usize> = HashMap::new();
+        for e in self.0.edge_references() {
+            let (kind, scenario) = match e.weight() {
+                Edge::ProjectSource(s) => (None, Some(s)),
+                Edge::UnitSource((k, s)) => (Some(*k), Some(s)),
+                _ => (None, None),
+            };
+            let key = (e.source(), e.target(), discriminant(e.weight()), kind);
+            let idx = *group_index.entry(key).or_insert_with(|| {
+                groups.push((key, e.weight().clone(), Vec::new()));
+                groups.len() - 1
+            });
+            if let Some(s) = scenario {
+                groups[idx].2.push(s.clone());
+            }
+
+            // Keep the strongest of parallel imports
+            if let (Edge::SourceImports(k1), Edge::SourceImports(k2)) =
+                (&mut groups[idx].1, e.weight())
+            {
+                *k1 = (*k1).max(*k2);
+            }
+        }
+
+        self.0.clear_edges();
+        for ((source, target, _, _), edge, scenarios) in groups {
+            if scenarios.is_empty() {
+                self.0.add_edge(source, target, edge);
+                continue;
+            }
+            for s in DepGraph::merge_scenarios(scenars, scenarios) {
+                self.0.add_edge(
+                    source,
+                    target,
+                    match &edge {
+                        Edge::UnitSource((k, _)) => Edge::UnitSource((*k, s)),
+                        _ => Edge::ProjectSource(s),
+                    },
+                );
+            }
+        }
+        before - self.0.edge_count()
+    }
+
+    /// Merge scenarios two by two, as long as their union is itself a
+    /// scenario.
+    fn merge_scenarios(
+        scenars: &AllScenarios,
+        scenarios: Vec<Scenario>,
+    ) -> Vec<Scenario> {
+        let mut result: Vec<Scenario> = Vec::new();
+        for s in scenarios {
+            let mut current = s;
+            // Merging might make it possible to merge with other scenarios
+            // that were already in the list.
+            while let Some((idx, merged)) =
+                result.iter().enumerate().find_map(|(idx, r)| {
+                    scenars.union(r, &current).map(|u| (idx, u))
+                })
+            {
+                result.swap_remove(idx);
+                current = merged;
+            }
+            result.push(current);
+        }
+        result
+    }
+
     /// Return all nodes in the graph, sorted topological (a node appears after
-    /// all the ones that import it)
-    pub fn toposort(&self) -> Vec<NodeIndex> {
-        toposort(&self.0, None).unwrap()
+    /// all the ones that import it).
+    /// Limited imports are ignored, since they are precisely meant to allow
+    /// cycles between projects.
+    /// On error, returns one of the cycles, as a list of nodes where each one
+    /// imports the next, and the last one imports the first.
+    pub fn toposort(&self) -> Result<Vec<NodeIndex>, Vec<NodeIndex>> {
+        let filtered = self.non_limited();
+        toposort(&filtered, None).map_err(|cycle| {
+            let start = cycle.node_id();
+            tarjan_scc(&filtered)
+                .into_iter()
+                .find(|scc| scc.contains(&start))
+                .and_then(|scc| self.cycle_in_component(&scc, start))
+                .unwrap_or_else(|| vec![start])
+        })
+    }
+
+    /// Find an actual cycle through start, among the nodes of its strongly
+    /// connected component.
+    fn cycle_in_component(
+        &self,
+        scc: &[NodeIndex],
+        start: NodeIndex,
+    ) -> Option<Vec<NodeIndex>> {
+        let filtered = self.non_limited();
+        let mut pred: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(n) = queue.pop_front() {
+            for next in filtered.neighbors(n) {
+                if next == start {
+                    let mut cycle = vec![n];
+                    while let Some(p) = pred.get(cycle.last().unwrap()) {
+                        cycle.push(*p);
+                    }
+                    cycle.reverse();
+                    return Some(cycle);
+                }
+                if scc.contains(&next) && !pred.contains_key(&next) {
+                    pred.insert(next, n);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
     }
 
     /// Return the list of dependencies for a node.
     /// Each dependency is reported only once (so if a project imports both A and
     /// B, which both import a common C, then C is only returned once).
     /// The returned value does not include start itself.
+    /// Projects only visible through a limited import are not included, since
+    /// their variables and attributes cannot be referenced.
     pub fn gpr_dependencies(&self, start: NodeIndex) -> Vec<NodeIndex> {
-        let mut bfs = Bfs::new(&self.0, start);
+        let filtered = self.non_limited();
+        let mut bfs = Bfs::new(&filtered, start);
         let mut result = Vec::new();
-        while let Some(node) = bfs.next(&self.0) {
+        while let Some(node) = bfs.next(&filtered) {
             if node != start {
                 result.push(node);
             }
@@ -94,6 +280,72 @@ impl DepGraph {
         result
     }
 
+    /// All projects whose sources are visible from start: start itself, and
+    /// all the projects it imports or extends, directly or not.
+    pub fn visible_projects(&self, start: NodeIndex) -> HashSet<NodeIndex> {
+        self.project_closure(start, |e| {
+            matches!(
+                e,
+                Edge::GPRImports | Edge::GPRLimitedImports | Edge::GPRExtends
+            )
+        })
+    }
+
+    /// All projects imported by start, directly or not, through non-limited
+    /// with clauses.  This includes start itself.
+    pub fn imported_projects(&self, start: NodeIndex) -> HashSet<NodeIndex> {
+        self.project_closure(start, |e| matches!(e, Edge::GPRImports))
+    }
+
+    /// The projects (other than gprnode) that own units imported by the
+    /// sources of gprnode, in any scenario.
+    pub fn projects_used_by_sources(
+        &self,
+        gprnode: NodeIndex,
+    ) -> HashSet<NodeIndex> {
+        self.0
+            .edges_directed(gprnode, Direction::Outgoing)
+            .filter(|e| matches!(e.weight(), Edge::ProjectSource(_)))
+            .flat_map(|e| {
+                self.0.edges_directed(e.target(), Direction::Outgoing)
+            })
+            .filter(|e| matches!(e.weight(), Edge::SourceImports(_)))
+            .flat_map(|e| self.projects_of_import(e.target()))
+            .filter(|p| *p != gprnode)
+            .collect()
+    }
+
+    /// All projects extended by start, directly or not.
+    pub fn extended_projects(&self, start: NodeIndex) -> HashSet<NodeIndex> {
+        let mut result =
+            self.project_closure(start, |e| matches!(e, Edge::GPRExtends));
+        result.remove(&start);
+        result
+    }
+
+    /// The set of nodes reachable from start, following only some of the
+    /// edges.  This includes start itself.
+    fn project_closure(
+        &self,
+        start: NodeIndex,
+        follow: fn(&Edge) -> bool,
+    ) -> HashSet<NodeIndex> {
+        let filtered = EdgeFiltered::from_fn(&self.0, |e| follow(e.weight()));
+        let mut bfs = Bfs::new(&filtered, start);
+        let mut result = HashSet::new();
+        while let Some(node) = bfs.next(&filtered) {
+            result.insert(node);
+        }
+        result
+    }
+
+    /// A view of the graph without the limited imports
+    fn non_limited(&self) -> EdgeFiltered<&G, fn(EdgeReference<Edge>) -> bool> {
+        EdgeFiltered(&self.0, |e| {
+            !matches!(e.weight(), Edge::GPRLimitedImports)
+        })
+    }
+
     // Iterate over source nodes
     //    pub fn iter_source_nodes(
     //        &self,
@@ -172,3 +424,78 @@ impl std::fmt::Debug for DepGraph {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::allscenarios::{
+        tests::{create_single, try_add_variable},
+        AllScenarios,
+    };
+    use crate::graph::{DepGraph, Edge, Node};
+    use petgraph::visit::EdgeRef;
+    use std::path::PathBuf;
+
+    #[test]
+    fn toposort_cycles() {
+        let mut g = DepGraph::default();
+        let a = g.add_node(Node::Project(PathBuf::from("a.gpr")));
+        let b = g.add_node(Node::Project(PathBuf::from("b.gpr")));
+        let c = g.add_node(Node::Project(PathBuf::from("c.gpr")));
+        g.add_edge(a, b, Edge::GPRImports);
+        g.add_edge(b, c, Edge::GPRExtends);
+        g.add_edge(c, a, Edge::GPRLimitedImports);
+        assert_eq!(g.toposort(), Ok(vec![a, b, c]));
+
+        // The cycle starts at the node where it was detected
+        g.add_edge(c, a, Edge::GPRImports);
+        assert_eq!(g.toposort(), Err(vec![c, a, b]));
+
+        let mut g = DepGraph::default();
+        let d = g.add_node(Node::Project(PathBuf::from("d.gpr")));
+        g.add_edge(d, d, Edge::GPRImports);
+        assert_eq!(g.toposort(), Err(vec![d]));
+    }
+
+    #[test]
+    fn merge_edges() {
+        let mut scenarios = AllScenarios::default();
+        try_add_variable(&mut scenarios, "MODE", &["debug", "lto", "optimize"]);
+        try_add_variable(&mut scenarios, "CHECK", &["most", "none"]);
+        let debug = create_single(&mut scenarios, "MODE", &["debug"]);
+        let lto = create_single(&mut scenarios, "MODE", &["lto"]);
+        let most = create_single(&mut scenarios, "CHECK", &["most"]);
+        let none = create_single(&mut scenarios, "CHECK", &["none"]);
+
+        let mut g = DepGraph::default();
+        let p = g.add_node(Node::Project(PathBuf::from("p.gpr")));
+        let a = g.add_node(Node::Source(PathBuf::from("a.ads")));
+        let b = g.add_node(Node::Source(PathBuf::from("b.ads")));
+        g.add_edge(p, a, Edge::ProjectSource(debug.clone()));
+        g.add_edge(p, a, Edge::ProjectSource(lto.clone()));
+        g.add_edge(p, b, Edge::ProjectSource(&debug & &most));
+        g.add_edge(p, b, Edge::ProjectSource(&lto & &none));
+        assert_eq!(g.merge_parallel_edges(&scenarios), 1);
+
+        // The scenarios of a.ads are combined, but not those of b.ads since
+        // both variables differ.
+        let mut edges: Vec<String> = g
+            .0
+            .edge_references()
+            .map(|e| match e.weight() {
+                Edge::ProjectSource(s) => {
+                    format!("{:?} {}", g.0[e.target()], scenarios.describe(s))
+                }
+                w => format!("{:?}", w),
+            })
+            .collect();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                "Source(\"a.ads\") MODE=debug|lto",
+                "Source(\"b.ads\") CHECK=most,MODE=debug",
+                "Source(\"b.ads\") CHECK=none,MODE=lto",
+            ]
+        );
+    }
+}
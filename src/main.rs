@@ -1,11 +1,19 @@
+mod action_affected;
+mod action_build_plan;
 mod action_check;
+mod action_closure;
+mod action_export;
 mod action_imported;
 mod action_path;
+mod action_redundant_withs;
 mod action_stats;
+mod action_unused_withs;
 mod ada_lexer;
 mod ada_scanner;
 mod allscenarios;
+mod asm_scanner;
 mod base_lexer;
+mod cache;
 mod cli;
 mod cpp_lexer;
 mod cpp_scanner;
@@ -14,16 +22,19 @@ mod environment;
 mod errors;
 mod files;
 mod findfile;
+mod fortran_scanner;
 mod gpr;
 mod gpr_scanner;
 mod graph;
 mod naming;
 mod packagename;
 mod perscenario;
+mod preprocessor;
 mod qnames;
 mod qualifiedname;
 mod rawexpr;
 mod rawgpr;
+mod scanners;
 mod scenario_variables;
 mod scenarios;
 mod settings;
@@ -47,19 +58,40 @@ fn main() -> Result<(), Error> {
     env.parse_all(&settings)?;
 
     match action {
+        Action::Affected(act) => {
+            act.perform(&env, &settings)?;
+        }
         Action::Stats(act) => {
             act.perform(&env, &settings)?;
         }
+        Action::Closure(act) => {
+            act.perform(&env, &settings)?;
+        }
         Action::Dependencies(act) => {
             act.perform(&env, &settings)?;
         }
+        Action::BuildPlan(act) => {
+            act.perform(&env, &settings)?;
+        }
         Action::Check(act) => {
             act.duplicates(&env, &settings)?;
+            act.ambiguous(&env, &settings)?;
+            act.bypassed_withs(&env, &settings)?;
+            act.spec_cycles(&env, &settings)?;
             act.unused(&env, &settings)?;
         }
         Action::ImportPath(act) => {
             act.perform(&env, &settings)?;
         }
+        Action::GraphExport(act) => {
+            act.perform(&env, &settings)?;
+        }
+        Action::GprRedundantWiths(act) => {
+            act.perform(&env, &settings)?;
+        }
+        Action::GprUnusedWiths(act) => {
+            act.perform(&env, &settings)?;
+        }
         Action::GprShow {
             gprpath,
             print_vars,
@@ -70,28 +102,5 @@ fn main() -> Result<(), Error> {
         }
     }
 
-    // TODO: should simplify edges to merge scenarios when possible.  Currently,
-    //    this merging is done in get_specs(), but it would be better to have it
-    //    directly in the graph instead.  See scenario in get_specs()
-    // TODO: support for GPR_PROJECT_PATH
-    //
-    // BUG: multiple units with the same name in the project tree would not
-    //    work (likely the graph is correct, but since we resolve dependencies
-    //    based on unit name, and not file name, we would not know which of the
-    //    two units we depend on).
-
-    // BUG: We should not be able to resolve system files, unless we use
-    // --runtime. As if we were looking up dependencies from projects we do not
-    // import.
-
-    //    let pool = threadpool::ThreadPool::new(1);
-    //    for gpr in list_of_gpr {
-    //        let gpr = gpr.clone();
-    //        pool.execute(move || {
-    //            let _ = parse_gpr_file(&gpr);
-    //        });
-    //    }
-    //    pool.join();
-
     Ok(())
 }
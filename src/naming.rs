@@ -1,11 +1,9 @@
 use crate::{
     directory::Directory, environment::Environment, errors::Error,
-    qnames::QName, sourcefile::SourceFile,
+    qnames::QName, sourcefile::SourceKind,
 };
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
 use ustr::Ustr;
 
 lazy_static::lazy_static! {
@@ -19,6 +17,7 @@ pub struct Naming {
     languages: Vec<Ustr>, // list of languages for this project
     source_dirs: Vec<PathBuf>, // source_dirs in this scenario
     source_files: Option<HashSet<Ustr>>, // source_files in this scenario
+    excluded_source_files: HashSet<Ustr>, // never sources in this scenario
     pub spec_suffix: HashMap<Ustr, Ustr>, // lang->spec suffix
     pub body_suffix: HashMap<Ustr, Ustr>, // lang->body suffix
     pub spec_files: HashMap<Ustr, Ustr>, // unit name -> spec file name
@@ -29,13 +28,13 @@ pub struct Naming {
     pub dot_replacement: Ustr,       // for Ada
 }
 
-/// Information for a source file in a project.  The `file` part is shared
-/// amongst all projects, but whether the file is a main depends on the
-/// project and the scenario.
+/// Information for a source file in a project.  The details of the file are
+/// shared amongst all projects and found from `path` in the environment, but
+/// whether the file is a main depends on the project and the scenario.
 /// This struct is for a single scenario.
 #[derive(Debug)]
 pub struct FileInGPR {
-    pub file: Rc<RefCell<SourceFile>>,
+    pub path: PathBuf,
     pub _is_main: bool,
 }
 
@@ -51,10 +50,46 @@ impl Naming {
         self.source_files = Some(files);
     }
 
+    pub fn set_excluded_source_files(&mut self, files: HashSet<Ustr>) {
+        self.excluded_source_files = files;
+    }
+
+    /// Whether the given basename is a valid source file, given the
+    /// Source_Files, Source_List_File and Excluded_Source_Files attributes.
+    pub fn is_source(&self, basename: &Ustr) -> bool {
+        !self.excluded_source_files.contains(basename)
+            && match &self.source_files {
+                None => true,
+                Some(sf) => sf.contains(basename),
+            }
+    }
+
     pub fn set_languages(&mut self, langs: Vec<Ustr>) {
         self.languages = langs;
     }
 
+    /// The unit of a C or C++ file, and the role of the file within it.  A
+    /// header and a body with the same base name are grouped in a single
+    /// unit, named after the header.
+    pub fn c_unit(
+        &self,
+        lang: Ustr,
+        basename: &str,
+    ) -> Option<(QName, SourceKind)> {
+        let spec = self.spec_suffix.get(&lang)?;
+        let (stem, kind) = match basename.strip_suffix(spec.as_str()) {
+            Some(stem) => (stem, SourceKind::Spec),
+            None => (
+                basename.strip_suffix(self.body_suffix.get(&lang)?.as_str())?,
+                SourceKind::Implementation,
+            ),
+        };
+        Some((
+            QName::new(vec![Ustr::from(&format!("{}{}", stem, spec))]),
+            kind,
+        ))
+    }
+
     fn register_source(
         &self,
         environ: &mut Environment,
@@ -62,11 +97,7 @@ impl Naming {
         basename: &Ustr,
         path: &Path,
     ) -> Result<Option<FileInGPR>, Error> {
-        let valid = match &self.source_files {
-            None => true,
-            Some(sf) => sf.contains(basename),
-        };
-        if valid {
+        if self.is_source(basename) {
             let is_main = match &self.main {
                 None => false,
                 Some(m) => m.contains(basename),
@@ -76,7 +107,7 @@ impl Naming {
                 s.borrow_mut().is_ever_main = is_main;
             }
             Ok(Some(FileInGPR {
-                file: s.clone(),
+                path: path.to_path_buf(),
                 _is_main: is_main,
             }))
         } else {
@@ -96,16 +127,18 @@ impl Naming {
         for d in &self.source_dirs {
             if let Some(dir) = all_dirs.get(d) {
                 for lang in &self.languages {
-                    for (b, p) in dir.find_suffix(&self.spec_suffix[lang]) {
-                        let s = self.register_source(env, *lang, b, p)?;
-                        if let Some(s) = s {
-                            files.push(s);
-                        }
-                    }
-                    for (b, p) in dir.find_suffix(&self.body_suffix[lang]) {
-                        let s = self.register_source(env, *lang, b, p)?;
-                        if let Some(s) = s {
-                            files.push(s);
+                    // Some languages (Fortran, assembler,...) have no spec
+                    let suffixes = self
+                        .spec_suffix
+                        .get(lang)
+                        .into_iter()
+                        .chain(self.body_suffix.get(lang));
+                    for suffix in suffixes {
+                        for (b, p) in dir.find_suffix(suffix) {
+                            let s = self.register_source(env, *lang, b, p)?;
+                            if let Some(s) = s {
+                                files.push(s);
+                            }
                         }
                     }
                 }
@@ -131,3 +164,134 @@ impl Naming {
         Ok(files)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::tests::parse_tree;
+    use crate::errors::Error;
+    use crate::graph::Edge;
+    use crate::naming::Naming;
+    use crate::sourcefile::SourceKind;
+    use petgraph::{visit::EdgeRef, Direction};
+    use ustr::Ustr;
+
+    #[test]
+    fn c_unit() {
+        let mut naming = Naming::default();
+        for (lang, spec, body) in [("c", ".h", ".c"), ("c++", ".hpp", ".cpp")] {
+            naming
+                .spec_suffix
+                .insert(Ustr::from(lang), Ustr::from(spec));
+            naming
+                .body_suffix
+                .insert(Ustr::from(lang), Ustr::from(body));
+        }
+        let unit = |lang: &str, basename: &str| {
+            naming
+                .c_unit(Ustr::from(lang), basename)
+                .map(|(u, k)| (u.to_string(), k))
+        };
+        assert_eq!(
+            unit("c", "foo.h"),
+            Some(("foo.h".into(), SourceKind::Spec))
+        );
+        assert_eq!(
+            unit("c", "foo.c"),
+            Some(("foo.h".into(), SourceKind::Implementation))
+        );
+        assert_eq!(
+            unit("c++", "foo.hpp"),
+            Some(("foo.hpp".into(), SourceKind::Spec))
+        );
+        assert_eq!(
+            unit("c++", "foo.cpp"),
+            Some(("foo.hpp".into(), SourceKind::Implementation))
+        );
+        assert_eq!(unit("c", "foo.txt"), None);
+        assert_eq!(unit("fortran", "foo.f90"), None);
+    }
+
+    #[test]
+    fn c_units_in_project() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "c_units",
+            &[
+                (
+                    "p.gpr",
+                    "project P is
+                        for Languages use (\"C\", \"C++\");
+                        package Naming is
+                           for Spec_Suffix (\"C++\") use \".hpp\";
+                        end Naming;
+                     end P;",
+                ),
+                ("foo.h", "int foo();\n"),
+                ("foo.c", "int foo() { return 0; }\n"),
+                ("bar.hpp", "int bar();\n"),
+                ("bar.cpp", "int bar() { return 0; }\n"),
+                ("alone.h", "#define ALONE 1\n"),
+            ],
+        )?;
+
+        // The sources of the unit of each file
+        let unit_files = |basename: &str| {
+            let unit = env.files[&settings.relto.join(basename)]
+                .borrow()
+                .unit_node
+                .unwrap();
+            let mut files: Vec<String> = env
+                .graph
+                .0
+                .edges_directed(unit, Direction::Outgoing)
+                .filter(|e| matches!(e.weight(), Edge::UnitSource(_)))
+                .map(|e| {
+                    settings
+                        .display_path(env.graph.get_source(e.target()).unwrap())
+                        .to_string()
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(unit_files("foo.c"), vec!["foo.c", "foo.h"]);
+        assert_eq!(unit_files("foo.h"), vec!["foo.c", "foo.h"]);
+        assert_eq!(unit_files("bar.cpp"), vec!["bar.cpp", "bar.hpp"]);
+        assert_eq!(unit_files("alone.h"), vec!["alone.h"]);
+        Ok(())
+    }
+
+    #[test]
+    fn fortran_default_naming() -> Result<(), Error> {
+        let (env, settings, _dir) = parse_tree(
+            "fortran_naming",
+            &[
+                (
+                    "p/p.gpr",
+                    "project P is for Languages use (\"Fortran\"); end P;",
+                ),
+                ("p/fixed.f", "      program fixed\n      end\n"),
+                ("p/free.f90", "program free\nend program\n"),
+                (
+                    "q/q.gpr",
+                    "project Q is
+                        for Languages use (\"Fortran\");
+                        package Naming is
+                           for Body_Suffix (\"Fortran\") use \".f90\";
+                        end Naming;
+                     end Q;",
+                ),
+                ("q/free.f90", "program free\nend program\n"),
+            ],
+        )?;
+
+        // As in gprbuild, only ".f" is a Fortran suffix by default
+        let mut files: Vec<String> = env
+            .files
+            .keys()
+            .map(|p| settings.display_path(p).to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["p/fixed.f", "q/free.f90"]);
+        Ok(())
+    }
+}
@@ -1,51 +1,141 @@
-// ??? Should implement std::ops::Index so that we can use it directly to
-// index arrays.
-
-use crate::errors::Error;
+use serde::{Deserialize, Serialize};
 use ustr::Ustr;
 
 lazy_static::lazy_static! {
     static ref BINDER:Ustr = Ustr::from("binder");
     static ref BUILDER:Ustr = Ustr::from("builder");
+    static ref CHECK:Ustr = Ustr::from("check");
+    static ref CLEAN:Ustr = Ustr::from("clean");
+    static ref CODEPEER:Ustr = Ustr::from("codepeer");
     static ref COMPILER:Ustr = Ustr::from("compiler");
+    static ref CROSS_REFERENCE:Ustr = Ustr::from("cross_reference");
+    static ref DOCUMENTATION:Ustr = Ustr::from("documentation");
+    static ref ELIMINATE:Ustr = Ustr::from("eliminate");
+    static ref EMULATOR:Ustr = Ustr::from("emulator");
+    static ref FINDER:Ustr = Ustr::from("finder");
+    static ref GNATLS:Ustr = Ustr::from("gnatls");
+    static ref GNATSTUB:Ustr = Ustr::from("gnatstub");
+    static ref GNATTEST:Ustr = Ustr::from("gnattest");
     static ref IDE:Ustr = Ustr::from("ide");
+    static ref INSTALL:Ustr = Ustr::from("install");
     static ref LINKER:Ustr = Ustr::from("linker");
+    static ref METRICS:Ustr = Ustr::from("metrics");
     static ref NAMING:Ustr = Ustr::from("naming");
+    static ref PRETTY_PRINTER:Ustr = Ustr::from("pretty_printer");
+    static ref PROVE:Ustr = Ustr::from("prove");
+    static ref REMOTE:Ustr = Ustr::from("remote");
+    static ref STACK:Ustr = Ustr::from("stack");
+    static ref SYNCHRONIZE:Ustr = Ustr::from("synchronize");
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-#[repr(usize)]
+/// The name of a package in a project file.
+/// All packages documented for gprbuild and the GNAT tools have their own
+/// variant, so that the scanner can tell them apart from project names.  Any
+/// other package is kept as `Other`, so that its attributes can still be
+/// displayed.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+)]
 pub enum PackageName {
-    None = 0,
+    None,
     Binder,
     Builder,
+    Check,
+    Clean,
+    Codepeer,
     Compiler,
+    CrossReference,
+    Documentation,
+    Eliminate,
+    Emulator,
+    Finder,
+    Gnatls,
+    Gnatstub,
+    Gnattest,
     Ide,
+    Install,
     Linker,
+    Metrics,
     Naming,
+    PrettyPrinter,
+    Prove,
+    Remote,
+    Stack,
+    Synchronize,
+    Other(#[serde(with = "crate::cache::ustr_serde")] Ustr), // lower-cased
 }
 
-// In rust nightly, we can use std::mem::variant_count::<PackageName>()
-pub const PACKAGE_NAME_VARIANTS: usize = 7;
-
 impl PackageName {
-    pub fn new(lower: Ustr) -> Result<Self, Error> {
+    /// Build a package name from its lower-cased name.  Unknown names are
+    /// accepted as `Other`.
+    pub fn new(lower: Ustr) -> Self {
         if lower == *BINDER {
-            Ok(PackageName::Binder)
+            PackageName::Binder
         } else if lower == *BUILDER {
-            Ok(PackageName::Builder)
+            PackageName::Builder
+        } else if lower == *CHECK {
+            PackageName::Check
+        } else if lower == *CLEAN {
+            PackageName::Clean
+        } else if lower == *CODEPEER {
+            PackageName::Codepeer
         } else if lower == *COMPILER {
-            Ok(PackageName::Compiler)
+            PackageName::Compiler
+        } else if lower == *CROSS_REFERENCE {
+            PackageName::CrossReference
+        } else if lower == *DOCUMENTATION {
+            PackageName::Documentation
+        } else if lower == *ELIMINATE {
+            PackageName::Eliminate
+        } else if lower == *EMULATOR {
+            PackageName::Emulator
+        } else if lower == *FINDER {
+            PackageName::Finder
+        } else if lower == *GNATLS {
+            PackageName::Gnatls
+        } else if lower == *GNATSTUB {
+            PackageName::Gnatstub
+        } else if lower == *GNATTEST {
+            PackageName::Gnattest
         } else if lower == *IDE {
-            Ok(PackageName::Ide)
+            PackageName::Ide
+        } else if lower == *INSTALL {
+            PackageName::Install
         } else if lower == *LINKER {
-            Ok(PackageName::Linker)
+            PackageName::Linker
+        } else if lower == *METRICS {
+            PackageName::Metrics
         } else if lower == *NAMING {
-            Ok(PackageName::Naming)
+            PackageName::Naming
+        } else if lower == *PRETTY_PRINTER {
+            PackageName::PrettyPrinter
+        } else if lower == *PROVE {
+            PackageName::Prove
+        } else if lower == *REMOTE {
+            PackageName::Remote
+        } else if lower == *STACK {
+            PackageName::Stack
+        } else if lower == *SYNCHRONIZE {
+            PackageName::Synchronize
         } else {
-            Err(Error::InvalidPackageName(lower))
+            PackageName::Other(lower)
         }
     }
+
+    /// Whether this is one of the packages known to gprbuild or the GNAT
+    /// tools.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, PackageName::Other(_))
+    }
 }
 
 impl std::fmt::Display for PackageName {
@@ -54,10 +144,29 @@ impl std::fmt::Display for PackageName {
             PackageName::None => write!(f, "<top>"),
             PackageName::Binder => write!(f, "binder"),
             PackageName::Builder => write!(f, "builder"),
+            PackageName::Check => write!(f, "check"),
+            PackageName::Clean => write!(f, "clean"),
+            PackageName::Codepeer => write!(f, "codepeer"),
             PackageName::Compiler => write!(f, "compiler"),
+            PackageName::CrossReference => write!(f, "cross_reference"),
+            PackageName::Documentation => write!(f, "documentation"),
+            PackageName::Eliminate => write!(f, "eliminate"),
+            PackageName::Emulator => write!(f, "emulator"),
+            PackageName::Finder => write!(f, "finder"),
+            PackageName::Gnatls => write!(f, "gnatls"),
+            PackageName::Gnatstub => write!(f, "gnatstub"),
+            PackageName::Gnattest => write!(f, "gnattest"),
             PackageName::Ide => write!(f, "ide"),
+            PackageName::Install => write!(f, "install"),
             PackageName::Linker => write!(f, "linker"),
+            PackageName::Metrics => write!(f, "metrics"),
             PackageName::Naming => write!(f, "naming"),
+            PackageName::PrettyPrinter => write!(f, "pretty_printer"),
+            PackageName::Prove => write!(f, "prove"),
+            PackageName::Remote => write!(f, "remote"),
+            PackageName::Stack => write!(f, "stack"),
+            PackageName::Synchronize => write!(f, "synchronize"),
+            PackageName::Other(name) => write!(f, "{}", name),
         }
     }
 }
@@ -41,7 +41,7 @@ impl<T> PerScenario<T> {
     /// Find all scenarios that result in different values in the project
     pub fn find_used_scenarios(&self, scenars: &mut HashSet<Scenario>) {
         for s in self.values.keys() {
-            scenars.insert(*s);
+            scenars.insert(s.clone());
         }
     }
 
@@ -54,11 +54,31 @@ impl<T> PerScenario<T> {
             values: self
                 .values
                 .iter()
-                .map(|(scenario, orig)| (*scenario, transform(orig)))
+                .map(|(scenario, orig)| (scenario.clone(), transform(orig)))
                 .collect(),
         }
     }
 
+    /// Transform the value into another value with the same scenarios,
+    /// stopping at the first error.
+    pub fn try_map<U, E, F>(
+        &self,
+        mut transform: F,
+    ) -> Result<PerScenario<U>, E>
+    where
+        F: FnMut(&T) -> Result<U, E>,
+    {
+        Ok(PerScenario {
+            values: self
+                .values
+                .iter()
+                .map(|(scenario, orig)| {
+                    Ok((scenario.clone(), transform(orig)?))
+                })
+                .collect::<Result<_, E>>()?,
+        })
+    }
+
     /// Display the value of a variable on two columns:
     ///     <indent>scenario1 value1<eol>
     ///     <indent>scenar2   value2<eol>
@@ -71,7 +91,7 @@ impl<T> PerScenario<T> {
     ) -> String {
         let mut col1 = Vec::new();
         for scenario in self.values.keys() {
-            col1.push(scenarios.describe(*scenario));
+            col1.push(scenarios.describe(scenario));
         }
         let max = col1.iter().map(String::len).max().unwrap_or(0);
         let mut lines = self
@@ -101,7 +121,7 @@ impl<T> PerScenario<T> {
     pub fn update<U, F>(
         &mut self,
         right: &PerScenario<U>,
-        context: Scenario,
+        context: &Scenario,
         scenars: &mut AllScenarios,
         merge: F,
     ) where
@@ -118,18 +138,19 @@ impl<T> PerScenario<T> {
             to_replace.extend(
                 self.values
                     .keys()
-                    .filter(|s1| !scenars.never_matches(*s1 & s)),
+                    .filter(|s1| !scenars.never_matches(&(*s1 & &s)))
+                    .cloned(),
             );
             for s1 in &to_replace {
                 if let Some(mut v1) = self.values.remove(s1) {
-                    for negated in scenars.negate(s) {
+                    for negated in scenars.negate(&s) {
                         let s1_neg = s1 & negated;
-                        if !scenars.never_matches(s1_neg) {
+                        if !scenars.never_matches(&s1_neg) {
                             self.values.insert(s1_neg, v1.clone());
                         }
                     }
                     merge(&mut v1, v2);
-                    self.values.insert(s1 & s, v1);
+                    self.values.insert(s1 & &s, v1);
                 }
             }
             to_replace.clear();
@@ -137,6 +158,16 @@ impl<T> PerScenario<T> {
     }
 }
 
+/// Build a value from its value in each scenario, for instance when reading
+/// it back from the cache.
+impl<T> FromIterator<(Scenario, T)> for PerScenario<T> {
+    fn from_iter<I: IntoIterator<Item = (Scenario, T)>>(iter: I) -> Self {
+        PerScenario {
+            values: iter.into_iter().collect(),
+        }
+    }
+}
+
 impl PerScenario<Ustr> {
     /// Create a new hashmap from a scenario variable.
     /// All possible values of the scenario variable must be provided.
@@ -146,13 +177,13 @@ impl PerScenario<Ustr> {
     /// the returned value is
     ///     {"e1=on": "on",  "e1=off": "off"}
     pub fn new_with_variable(
-        full_mask: Scenario,
+        full_mask: &Scenario,
         values: &[(Ustr, Scenario)],
     ) -> Self {
         let base = Scenario::default() & !full_mask;
         let mut m = HashMap::new();
         for (u, s) in values {
-            m.insert(s | base, *u);
+            m.insert(s | &base, *u);
         }
         PerScenario { values: m }
     }
@@ -170,7 +201,7 @@ where
         let mut res = String::new();
         res.push('{');
         for (s, v) in items {
-            res.push_str(&scenars.describe(*s));
+            res.push_str(&scenars.describe(s));
             res.push(':');
             let _ = write!(res, "{:?}", v); // ignore errors in tests
             res.push_str(", ");
@@ -201,7 +232,7 @@ mod tests {
         // Case of doing   V := 1  at the top level.
         let one = PerScenario::<u8>::new(1);
         let mut v = zero.clone();
-        v.update(&one, Scenario::default(), &mut scenars, |old, new| {
+        v.update(&one, &Scenario::default(), &mut scenars, |old, new| {
             *old = *new
         });
         assert_eq!(v.format(&scenars), "{*:1, }",);
@@ -212,7 +243,7 @@ mod tests {
         let ctx = create_single(&mut scenars, "E1", &["a", "b"]);
         let mut v2 = v.clone();
         let two = PerScenario::<u8>::new(2);
-        v2.update(&two, ctx, &mut scenars, |old, new| *old = *new);
+        v2.update(&two, &ctx, &mut scenars, |old, new| *old = *new);
         assert_eq!(v2.format(&scenars), "{E1=a|b:2, E1=c|d:1, }",);
 
         // Now use the above in another case statement.
@@ -226,7 +257,7 @@ mod tests {
         // in merge().
         let ctx = create_single(&mut scenars, "E2", &["e"]);
         let mut v3 = PerScenario::new(vec![]);
-        v3.update(&v2, ctx, &mut scenars, |old, new| old.push(*new));
+        v3.update(&v2, &ctx, &mut scenars, |old, new| old.push(*new));
         let out = v3.format(&scenars);
         let expect1 =
             "{E1=a|b,E2=e:[2], E1=c|d,E2=e:[1], E1=c|d,E2=f:[], E2=f:[], }";
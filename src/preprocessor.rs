@@ -0,0 +1,219 @@
+//! Evaluation of the conditions in C preprocessor directives (#if, #elif,...)
+//! We only know about the macros given on the compiler's command line, while
+//! other macros might be defined by the compiler itself or by some header.
+//! So a macro that is neither given with -D nor -U is unknown, and so is any
+//! condition that depends on it.  For instance "defined(A) || B" is true when
+//! A is given with -D, whatever the value of B.
+
+use std::collections::HashMap;
+use ustr::Ustr;
+
+/// The macros from the command line, and their value (None for -U)
+pub type Macros = HashMap<Ustr, Option<Ustr>>;
+
+// The value of an expression, or None if it depends on unknown macros
+type Value = Option<i64>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(Ustr),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 17] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "%",
+    "(", ")", "~",
+];
+
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let mut result = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let num = rest[..len].trim_end_matches(['u', 'U', 'l', 'L']);
+            result.push(Token::Number(
+                match num.strip_prefix("0x").or(num.strip_prefix("0X")) {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                    None if num.len() > 1 && num.starts_with('0') => {
+                        i64::from_str_radix(&num[1..], 8).ok()?
+                    }
+                    None => num.parse().ok()?,
+                },
+            ));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            result.push(Token::Ident(Ustr::from(&rest[..len])));
+            len
+        } else {
+            let op = OPERATORS.iter().find(|op| rest.starts_with(**op))?;
+            result.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Some(result)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    macros: &'a Macros,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Option<()> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Parse a binary expression, with operators of at least the given
+    /// precedence.  Returns None on syntax errors.
+    fn binary(&mut self, min_prec: u8) -> Option<Value> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_op() {
+            let prec = match op {
+                "||" => 1,
+                "&&" => 2,
+                "==" | "!=" => 3,
+                "<" | ">" | "<=" | ">=" => 4,
+                "+" | "-" => 5,
+                "*" | "/" | "%" => 6,
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let right = self.binary(prec + 1)?;
+            left = match (op, left, right) {
+                // Short-circuit operators may have a value even if one of
+                // their operands is unknown
+                ("||", Some(l), _) if l != 0 => Some(1),
+                ("||", _, Some(r)) if r != 0 => Some(1),
+                ("&&", Some(0), _) | ("&&", _, Some(0)) => Some(0),
+                (_, Some(l), Some(r)) => match op {
+                    "||" => Some((l != 0 || r != 0) as i64),
+                    "&&" => Some((l != 0 && r != 0) as i64),
+                    "==" => Some((l == r) as i64),
+                    "!=" => Some((l != r) as i64),
+                    "<" => Some((l < r) as i64),
+                    ">" => Some((l > r) as i64),
+                    "<=" => Some((l <= r) as i64),
+                    ">=" => Some((l >= r) as i64),
+                    "+" => l.checked_add(r),
+                    "-" => l.checked_sub(r),
+                    "*" => l.checked_mul(r),
+                    "/" => l.checked_div(r),
+                    _ => l.checked_rem(r),
+                },
+                _ => None,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Value> {
+        let tk = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match tk {
+            Token::Number(n) => Some(Some(n)),
+            Token::Op("!") => Some(self.unary()?.map(|v| (v == 0) as i64)),
+            Token::Op("-") => Some(self.unary()?.and_then(i64::checked_neg)),
+            Token::Op("~") => Some(self.unary()?.map(|v| !v)),
+            Token::Op("(") => {
+                let v = self.binary(1)?;
+                self.expect(")")?;
+                Some(v)
+            }
+            Token::Ident(n) if n == "defined" => {
+                let paren = self.expect("(").is_some();
+                let Some(Token::Ident(name)) =
+                    self.tokens.get(self.pos).cloned()
+                else {
+                    return None;
+                };
+                self.pos += 1;
+                if paren {
+                    self.expect(")")?;
+                }
+                Some(self.macros.get(&name).map(|v| v.is_some() as i64))
+            }
+            Token::Ident(n) => Some(match self.macros.get(&n) {
+                None => None,
+                Some(None) => Some(0), // undefined macros evaluate to 0
+                // Only simple values are supported for macros
+                Some(Some(v)) => match tokenize(v).as_deref() {
+                    Some([Token::Number(n)]) => Some(*n),
+                    _ => None,
+                },
+            }),
+            Token::Op(_) => None,
+        }
+    }
+}
+
+/// Evaluate the condition of an #if directive.  This returns None if the
+/// condition depends on unknown macros or cannot be parsed.
+pub fn evaluate(expr: &str, macros: &Macros) -> Option<bool> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        macros,
+    };
+    let v = parser.binary(1)?;
+    if parser.pos == parser.tokens.len() {
+        v.map(|v| v != 0)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, Macros};
+    use ustr::Ustr;
+
+    #[test]
+    fn test_evaluate() {
+        let mut macros = Macros::new();
+        macros.insert(Ustr::from("A"), Some(Ustr::from("1")));
+        macros.insert(Ustr::from("V"), Some(Ustr::from("0x10")));
+        macros.insert(Ustr::from("U"), None);
+
+        assert_eq!(evaluate("defined(A)", &macros), Some(true));
+        assert_eq!(evaluate("defined A && !defined(U)", &macros), Some(true));
+        assert_eq!(evaluate("!defined(A)", &macros), Some(false));
+        assert_eq!(evaluate("V >= 16 && V < 0x20", &macros), Some(true));
+        assert_eq!(evaluate("U == 0", &macros), Some(true));
+        assert_eq!(evaluate("(1 + 2) * 3 == 9", &macros), Some(true));
+
+        // Unknown macros
+        assert_eq!(evaluate("defined(B)", &macros), None);
+        assert_eq!(evaluate("defined(B) || A", &macros), Some(true));
+        assert_eq!(evaluate("B && !A", &macros), Some(false));
+        assert_eq!(evaluate("B > 1", &macros), None);
+
+        // Unsupported syntax
+        assert_eq!(evaluate("A ? 1 : 0", &macros), None);
+        assert_eq!(evaluate("(A", &macros), None);
+    }
+}
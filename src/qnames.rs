@@ -5,14 +5,15 @@
 //! For Ada, one unit will include the spec (.ads), the body (.adb), and any
 //! number of separates, since a `with` statement mentions a unit name.
 //!
-//! For C, each file is its own unit, since a `#import` mentions a file path.
+//! For C, a header and the body with the same base name form a unit, named
+//! after the header, though an `#include` depends on the header file itself.
 //!
 //! For Rust, each file it is own unit, the name of which is given by the
 //! crate's fully qualified name "crate::errors::Error" for instance.
 use itertools::join;
 use ustr::Ustr;
 
-#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct QName(pub Vec<Ustr>);
 
 impl QName {
@@ -32,9 +33,11 @@ impl QName {
         self.0.extend(child.0);
     }
 
+    /// The parent unit, if any.  Toplevel units have no parent, rather than
+    /// an unnamed one that every toplevel unit would depend on.
     pub fn parent(&self) -> Option<QName> {
         match self.0.len() {
-            0 => None,
+            0 | 1 => None,
             s => Some(QName::from_slice(&self.0[0..s - 1])),
         }
     }
@@ -45,3 +48,21 @@ impl std::fmt::Display for QName {
         write!(f, "{}", join(self.0.iter(), "."))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::qnames::QName;
+    use ustr::Ustr;
+
+    #[test]
+    fn parent() {
+        let name = |s: &str| QName::from_str(&Ustr::from(s), ".");
+        assert_eq!(
+            name("ada.text_io.enum_io").parent(),
+            Some(name("ada.text_io"))
+        );
+        assert_eq!(name("ada.text_io").parent(), Some(name("ada")));
+        assert_eq!(name("ada").parent(), None);
+        assert_eq!(QName::default().parent(), None);
+    }
+}
@@ -32,8 +32,13 @@ pub struct QualifiedName {
 impl QualifiedName {
     /// When we find a name in the source which an optional leading identifier,
     /// the latter could be either a project or a package.  This function will
-    /// guess as needed.
-    pub fn from_two(prj_or_pkg: Option<Ustr>, name: SimpleName) -> Self {
+    /// guess as needed: it is a package if it is one of the predefined ones,
+    /// or one of the packages already declared in the current project.
+    pub fn from_two(
+        prj_or_pkg: Option<Ustr>,
+        name: SimpleName,
+        packages: &[PackageName],
+    ) -> Self {
         match prj_or_pkg {
             None => QualifiedName {
                 project: prj_or_pkg,
@@ -41,12 +46,12 @@ impl QualifiedName {
                 name,
             },
             Some(n1) => match PackageName::new(n1) {
-                Ok(p) => QualifiedName {
+                p if p.is_known() || packages.contains(&p) => QualifiedName {
                     project: None,
                     package: p,
                     name,
                 },
-                Err(_) => QualifiedName {
+                _ => QualifiedName {
                     project: Some(n1),
                     package: PackageName::None,
                     name,
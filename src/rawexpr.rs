@@ -62,14 +62,22 @@ lazy_static::lazy_static! {
 impl RawExpr {
     /// Whether the expression contains a call to external().
     /// Returns the name of the scenario variable, and the default value.
-    pub fn has_external(&self) -> Option<(Ustr, Option<Ustr>)> {
+    pub fn has_external(&self) -> Result<Option<(Ustr, Option<Ustr>)>, Error> {
         match self {
             // ??? Fails if we have two calls to external
             //     external("e1") & external("e2")
-            RawExpr::Ampersand((left, right)) => {
-                left.has_external().or_else(|| right.has_external())
+            RawExpr::Ampersand((left, right)) => match left.has_external()? {
+                None => right.has_external(),
+                found => Ok(found),
+            },
+            RawExpr::List(v) => {
+                for e in v {
+                    if let Some(found) = e.has_external()? {
+                        return Ok(Some(found));
+                    }
+                }
+                Ok(None)
             }
-            RawExpr::List(v) => v.iter().find_map(|e| e.has_external()),
             RawExpr::FuncCall((
                 QualifiedName {
                     project: None,
@@ -77,31 +85,18 @@ impl RawExpr {
                     name: SimpleName::Name(n),
                 },
                 args,
-            )) => {
-                if *n == *EXTERNAL {
-                    match &args[0] {
-                        RawExpr::Str(s) => match args.get(1) {
-                            None => Some((*s, None)),
-                            Some(RawExpr::Str(default)) => {
-                                Some((*s, Some(*default)))
-                            }
-                            Some(RawExpr::Name(q)) => {
-                                Some((*s, Some(Ustr::from(&format!("{}", q)))))
-                            }
-                            _ => panic!(
-                                "Second arg to external must be static string"
-                            ),
-                        },
-                        _ => panic!(
-                            "First argument to external must \
-                                 be static string"
-                        ),
-                    }
-                } else {
-                    None
+            )) if *n == *EXTERNAL => match (args.first(), args.get(1)) {
+                (Some(RawExpr::Str(s)), None) => Ok(Some((*s, None))),
+                (Some(RawExpr::Str(s)), Some(RawExpr::Str(default))) => {
+                    Ok(Some((*s, Some(*default))))
                 }
-            }
-            _ => None,
+                (Some(RawExpr::Str(s)), Some(RawExpr::Name(q))) => {
+                    Ok(Some((*s, Some(Ustr::from(&format!("{}", q))))))
+                }
+                (None, _) => Err(Error::WrongFunctionArguments(*n)),
+                (Some(_), _) => Err(Error::NotStaticString),
+            },
+            _ => Ok(None),
         }
     }
 
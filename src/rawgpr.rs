@@ -3,6 +3,7 @@
 //! Such an object is only valid as long as the scanner that generates it, since
 //! it references memory from that scanner directly.
 use crate::rawexpr::StatementList;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use ustr::Ustr;
 
@@ -10,11 +11,16 @@ use ustr::Ustr;
 pub struct RawGPR {
     pub path: std::path::PathBuf,
     pub imported: Vec<PathBuf>,
+    pub limited_imported: Vec<PathBuf>,
     pub name: Ustr,
     pub is_abstract: bool,
     pub is_aggregate: bool,
     pub is_library: bool,
     pub extends: Option<PathBuf>,
+    pub extends_all: bool,
+
+    // Line of the "with" clause or "extends" declaration for each dependency
+    pub dep_lines: HashMap<PathBuf, u32>,
     pub body: StatementList,
 }
 
@@ -24,11 +30,14 @@ impl RawGPR {
         Self {
             path: path.to_path_buf(),
             imported: vec![],
+            limited_imported: vec![],
             name: Default::default(),
             is_abstract: false,
             is_aggregate: false,
             is_library: false,
             extends: None,
+            extends_all: false,
+            dep_lines: HashMap::new(),
             body: vec![],
         }
     }
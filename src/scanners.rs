@@ -0,0 +1,116 @@
+//! The registry of source scanners, one per language.
+//! Each scanner extracts the unit name and dependencies of a source file.  To
+//! support a new language, implement `LanguageScanner` and register it in
+//! `ScannerRegistry::default()`.
+
+use crate::{
+    ada_lexer::{AdaLexer, AdaLexerOptions},
+    ada_scanner::AdaScanner,
+    asm_scanner::AsmScanner,
+    cpp_lexer::CppLexer,
+    cpp_scanner::CppScanner,
+    errors::Error,
+    files::File,
+    fortran_scanner::FortranScanner,
+    qnames::QName,
+    sourcefile::{Include, ParseResult, SourceKind},
+};
+use std::collections::HashMap;
+use std::path::Path;
+use ustr::Ustr;
+
+lazy_static::lazy_static! {
+    pub static ref SCANNERS: ScannerRegistry = ScannerRegistry::default();
+}
+
+/// Extract the unit name and dependencies of source files in one language.
+/// Scanners are shared by all the threads that parse files.
+pub trait LanguageScanner: Send + Sync {
+    fn parse(&self, file: &mut File, path: &Path)
+        -> Result<ParseResult, Error>;
+}
+
+struct AdaLanguage;
+
+impl LanguageScanner for AdaLanguage {
+    fn parse(&self, file: &mut File, _: &Path) -> Result<ParseResult, Error> {
+        AdaScanner::parse(AdaLexer::new(
+            file,
+            AdaLexerOptions {
+                kw_aggregate: false,
+                kw_body: true,
+            },
+        )?)
+    }
+}
+
+struct CppLanguage;
+
+impl LanguageScanner for CppLanguage {
+    fn parse(
+        &self,
+        file: &mut File,
+        path: &Path,
+    ) -> Result<ParseResult, Error> {
+        CppScanner::parse(CppLexer::new(file)?, path)
+    }
+}
+
+pub struct ScannerRegistry {
+    scanners: HashMap<Ustr, Box<dyn LanguageScanner>>, // lower-cased lang
+}
+
+impl Default for ScannerRegistry {
+    fn default() -> Self {
+        let mut reg = ScannerRegistry {
+            scanners: HashMap::new(),
+        };
+        reg.register("ada", Box::new(AdaLanguage));
+        reg.register("c", Box::new(CppLanguage));
+        reg.register("c++", Box::new(CppLanguage));
+        reg.register("fortran", Box::new(FortranScanner));
+        reg.register("asm", Box::new(AsmScanner));
+        reg.register("asm2", Box::new(AsmScanner));
+        reg.register("asm_cpp", Box::new(AsmScanner));
+        reg
+    }
+}
+
+impl ScannerRegistry {
+    pub fn register(&mut self, lang: &str, scanner: Box<dyn LanguageScanner>) {
+        self.scanners
+            .insert(Ustr::from(&lang.to_lowercase()), scanner);
+    }
+
+    /// Parse a source file.  Files in languages we have no scanner for are
+    /// not even read: they have no unit and no dependencies.
+    pub fn parse(&self, path: &Path, lang: Ustr) -> Result<ParseResult, Error> {
+        match self.scanners.get(&lang) {
+            None => Ok(ParseResult {
+                unitname: QName::default(),
+                kind: SourceKind::Implementation,
+                deps: Default::default(),
+                includes: Default::default(),
+            }),
+            Some(scanner) => scanner.parse(&mut File::new(path)?, path),
+        }
+    }
+}
+
+/// Parse a line of the form `#include "file"` or `#include <file>`, as
+/// handled by the C preprocessor in languages other than C.
+pub fn parse_hash_include(line: &str) -> Option<Include> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim_start();
+    let (system, endquote) = match rest.chars().next()? {
+        '"' => (false, '"'),
+        '<' => (true, '>'),
+        _ => return None,
+    };
+    let path = rest[1..].split(endquote).next()?;
+    Some(Include {
+        path: Ustr::from(path),
+        system,
+        condition: None,
+    })
+}
@@ -2,6 +2,7 @@ use crate::{perscenario::PerScenario, scenarios::Scenario, values::ExprValue};
 use itertools::join;
 use ustr::Ustr;
 
+#[derive(Clone)]
 pub struct ScenarioVariable {
     name: Ustr,
     valid: Vec<(Ustr, Scenario)>,
@@ -42,7 +43,7 @@ impl ScenarioVariable {
         default: Option<Ustr>,
     ) -> Self {
         let value =
-            ExprValue::Str(PerScenario::new_with_variable(full_mask, &valid));
+            ExprValue::Str(PerScenario::new_with_variable(&full_mask, &valid));
         ScenarioVariable {
             name,
             valid,
@@ -76,8 +77,8 @@ impl ScenarioVariable {
     }
 
     /// Describe the mask using the actual values (for debug purposes)
-    pub fn describe(&self, scenario: Scenario) -> String {
-        if (scenario & self.full_mask) == self.full_mask {
+    pub fn describe(&self, scenario: &Scenario) -> String {
+        if (scenario & &self.full_mask) == self.full_mask {
             String::new()
         } else {
             format!(
@@ -103,16 +104,25 @@ impl ScenarioVariable {
         &self.name
     }
 
+    /// The values of the variable that are part of the scenario
+    pub fn values_in(&self, scenario: &Scenario) -> Vec<Ustr> {
+        self.valid
+            .iter()
+            .filter(|(_, mask)| !(scenario & mask).is_empty())
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
     /// The mask for one specific value of the variable
     pub fn mask(&self, value: &Ustr) -> Scenario {
         match self.valid.iter().find(|(val, _)| val == value) {
             None => Scenario::empty(),
-            Some(item) => item.1,
+            Some(item) => item.1.clone(),
         }
     }
 
-    pub fn full_mask(&self) -> Scenario {
-        self.full_mask
+    pub fn full_mask(&self) -> &Scenario {
+        &self.full_mask
     }
 
     /// Display the variable, its list of values, and the default
@@ -6,17 +6,66 @@
 //! like:
 //!     [0 1 1][0 1][0 0 ....]
 
-use crate::errors::Error;
+//! The bitmask has no fixed width: bits beyond the stored words all have the
+//! same value (set for scenarios that apply to all values of variables that
+//! are not mentioned explicitly).  Up to 64 values, no memory allocation
+//! occurs.
 
-type Mask = u64;
-pub const MAX_VALUES: u32 = Mask::BITS;
+use smallvec::SmallVec;
 
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
-pub struct Scenario(Mask);
+type Word = u64;
+type Words = SmallVec<[Word; 1]>;
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Scenario {
+    // Always normalized: the last word is never equal to the filler.
+    words: Words,
+    rest: bool, // value of all bits beyond `words`
+}
 
 impl ::core::fmt::Debug for Scenario {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:b}", self.0)
+        if self.words.is_empty() {
+            return write!(f, "{}", if self.rest { "1..1" } else { "0" });
+        }
+        if self.rest {
+            write!(f, "1..")?;
+        }
+        for (idx, w) in self.words.iter().rev().enumerate() {
+            if idx == 0 && !self.rest {
+                write!(f, "{:b}", w)?;
+            } else {
+                write!(f, "{:064b}", w)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scenarios are ordered as the (infinitely wide) numbers they represent
+impl Ord for Scenario {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let lfill = Scenario::filler(self.rest);
+        let rfill = Scenario::filler(other.rest);
+        let len = self.words.len().max(other.words.len());
+        self.rest.cmp(&other.rest).then_with(|| {
+            (0..len)
+                .rev()
+                .map(|idx| {
+                    self.words
+                        .get(idx)
+                        .unwrap_or(&lfill)
+                        .cmp(other.words.get(idx).unwrap_or(&rfill))
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+}
+
+impl PartialOrd for Scenario {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -24,42 +73,80 @@ impl Default for Scenario {
     /// The default value is a scenario that applies to all values for all
     /// variables.
     fn default() -> Self {
-        Scenario(Mask::MAX)
+        Scenario {
+            words: Words::new(),
+            rest: true,
+        }
     }
 }
 
 impl Scenario {
     /// A scenario that never applies
     pub fn empty() -> Self {
-        Scenario(0)
+        Scenario {
+            words: Words::new(),
+            rest: false,
+        }
     }
 
     /// True if the scenario never applies for any of the variables.
     /// Note that there are cases where a scenario might not apply because one
     /// of the variable has no matching value, but this won't be detected.
     pub fn is_empty(&self) -> bool {
-        self.0 == 0
+        !self.rest && self.words.is_empty()
     }
-}
 
-pub struct ScenarioFactory {
-    next_mask: Mask,
-}
+    fn filler(rest: bool) -> Word {
+        if rest {
+            Word::MAX
+        } else {
+            0
+        }
+    }
 
-impl Default for ScenarioFactory {
-    fn default() -> Self {
-        ScenarioFactory { next_mask: 1 }
+    /// Combine two scenarios bit by bit
+    fn combine<F>(&self, rhs: &Scenario, op: F) -> Scenario
+    where
+        F: Fn(Word, Word) -> Word,
+    {
+        let lfill = Scenario::filler(self.rest);
+        let rfill = Scenario::filler(rhs.rest);
+        let len = self.words.len().max(rhs.words.len());
+        let mut result = Scenario {
+            words: (0..len)
+                .map(|idx| {
+                    op(
+                        *self.words.get(idx).unwrap_or(&lfill),
+                        *rhs.words.get(idx).unwrap_or(&rfill),
+                    )
+                })
+                .collect(),
+            rest: op(lfill, rfill) != 0,
+        };
+        result.normalize();
+        result
+    }
+
+    fn normalize(&mut self) {
+        let fill = Scenario::filler(self.rest);
+        while self.words.last() == Some(&fill) {
+            self.words.pop();
+        }
     }
 }
 
+#[derive(Clone, Default)]
+pub struct ScenarioFactory {
+    next_bit: usize,
+}
+
 impl ScenarioFactory {
-    pub fn get_next(&mut self) -> Result<Scenario, Error> {
-        let s = Scenario(self.next_mask);
-        match self.next_mask.checked_mul(2) {
-            None => Err(Error::TooManyScenarioVariables)?,
-            Some(u) => self.next_mask = u,
-        }
-        Ok(s)
+    pub fn get_next(&mut self) -> Scenario {
+        let word = self.next_bit / Word::BITS as usize;
+        let mut words = Words::from_elem(0, word + 1);
+        words[word] = 1 << (self.next_bit % Word::BITS as usize);
+        self.next_bit += 1;
+        Scenario { words, rest: false }
     }
 }
 
@@ -69,7 +156,7 @@ impl ::core::ops::BitAnd<Scenario> for Scenario {
     type Output = Scenario;
 
     fn bitand(self, rhs: Scenario) -> Self::Output {
-        Scenario(self.0 & rhs.0)
+        self.combine(&rhs, |a, b| a & b)
     }
 }
 
@@ -77,7 +164,7 @@ impl ::core::ops::BitAnd<&Scenario> for &Scenario {
     type Output = Scenario;
 
     fn bitand(self, rhs: &Scenario) -> Self::Output {
-        Scenario(self.0 & rhs.0)
+        self.combine(rhs, |a, b| a & b)
     }
 }
 
@@ -85,7 +172,7 @@ impl ::core::ops::BitAnd<Scenario> for &Scenario {
     type Output = Scenario;
 
     fn bitand(self, rhs: Scenario) -> Self::Output {
-        Scenario(self.0 & rhs.0)
+        self.combine(&rhs, |a, b| a & b)
     }
 }
 
@@ -93,7 +180,7 @@ impl ::core::ops::BitAnd<&Scenario> for Scenario {
     type Output = Scenario;
 
     fn bitand(self, rhs: &Scenario) -> Self::Output {
-        Scenario(self.0 & rhs.0)
+        self.combine(rhs, |a, b| a & b)
     }
 }
 
@@ -101,7 +188,7 @@ impl ::core::ops::BitOr<Scenario> for Scenario {
     type Output = Scenario;
 
     fn bitor(self, rhs: Scenario) -> Self::Output {
-        Scenario(self.0 | rhs.0)
+        self.combine(&rhs, |a, b| a | b)
     }
 }
 
@@ -109,7 +196,34 @@ impl ::core::ops::BitOr<Scenario> for &Scenario {
     type Output = Scenario;
 
     fn bitor(self, rhs: Scenario) -> Self::Output {
-        Scenario(self.0 | rhs.0)
+        self.combine(&rhs, |a, b| a | b)
+    }
+}
+
+impl ::core::ops::BitOr<&Scenario> for &Scenario {
+    type Output = Scenario;
+
+    fn bitor(self, rhs: &Scenario) -> Self::Output {
+        self.combine(rhs, |a, b| a | b)
+    }
+}
+
+impl ::core::ops::BitOr<&Scenario> for Scenario {
+    type Output = Scenario;
+
+    fn bitor(self, rhs: &Scenario) -> Self::Output {
+        self.combine(rhs, |a, b| a | b)
+    }
+}
+
+impl ::core::ops::Not for &Scenario {
+    type Output = Scenario;
+
+    fn not(self) -> Self::Output {
+        Scenario {
+            words: self.words.iter().map(|w| !w).collect(),
+            rest: !self.rest,
+        }
     }
 }
 
@@ -117,6 +231,9 @@ impl ::core::ops::Not for Scenario {
     type Output = Scenario;
 
     fn not(self) -> Self::Output {
-        Scenario(!self.0)
+        Scenario {
+            words: self.words.iter().map(|w| !w).collect(),
+            rest: !self.rest,
+        }
     }
 }
@@ -19,6 +19,11 @@ pub struct Settings {
     // for runtime files for the various languages.
     pub runtime_gpr: Vec<PathBuf>,
 
+    // Directories searched for imported projects that are not found relative
+    // to the importing project.  This contains the --project-path switches,
+    // then GPR_PROJECT_PATH and ADA_PROJECT_PATH, in that order.
+    pub project_path: Vec<PathBuf>,
+
     // The root directory, underneath which we look for all project files
     pub root: Vec<PathBuf>,
 
@@ -34,6 +39,10 @@ pub struct Settings {
     // A subset of scenarios used in queries.  By default we query for all
     // scenarios.
     pub variables: HashMap<String, Vec<String>>,
+
+    // Where to store the parsed source files, so that only the modified ones
+    // are parsed again in the next run.
+    pub cache: Option<PathBuf>,
 }
 
 impl Settings {
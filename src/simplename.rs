@@ -1,10 +1,11 @@
 //! An unqualified name, which could be either an attribute or variable
 use crate::errors::Error;
+use serde::{Deserialize, Serialize};
 use ustr::Ustr;
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub enum StringOrOthers {
-    Str(Ustr),
+    Str(#[serde(with = "crate::cache::ustr_serde")] Ustr),
     Others,
 }
 impl std::fmt::Display for StringOrOthers {
@@ -28,6 +29,9 @@ lazy_static::lazy_static! {
     static ref EXTERNALLY_BUILT: Ustr = Ustr::from("externally_built");
     static ref GLOBAL_CONFIGURATION_PRAGMAS: Ustr =
         Ustr::from("global_configuration_pragmas");
+    static ref IMPLEMENTATION: Ustr = Ustr::from("implementation");
+    static ref IMPLEMENTATION_SUFFIX: Ustr =
+        Ustr::from("implementation_suffix");
     static ref LANGUAGES: Ustr = Ustr::from("languages");
     static ref LIBRARY_DIR: Ustr = Ustr::from("library_dir");
     static ref LIBRARY_INTERFACE: Ustr = Ustr::from("library_interface");
@@ -39,6 +43,8 @@ lazy_static::lazy_static! {
     static ref LINKER_OPTIONS: Ustr = Ustr::from("linker_options");
     static ref LOCAL_CONFIGURATION_PRAGMAS: Ustr =
         Ustr::from("local_configuration_pragmas");
+    static ref LOCALLY_REMOVED_FILES: Ustr =
+        Ustr::from("locally_removed_files");
     static ref MAIN: Ustr = Ustr::from("main");
     static ref OBJECT_DIR: Ustr = Ustr::from("object_dir");
     static ref PROJECT_FILES: Ustr = Ustr::from("project_files");
@@ -49,24 +55,61 @@ lazy_static::lazy_static! {
     static ref SOURCE_LIST_FILE: Ustr = Ustr::from("source_list_file");
     static ref SPEC: Ustr = Ustr::from("spec");
     static ref SPEC_SUFFIX: Ustr = Ustr::from("spec_suffix");
+    static ref SPECIFICATION: Ustr = Ustr::from("specification");
+    static ref SPECIFICATION_SUFFIX: Ustr =
+        Ustr::from("specification_suffix");
     static ref SWITCHES: Ustr = Ustr::from("switches");
     static ref TARGET: Ustr = Ustr::from("target");
     static ref VCS_KIND: Ustr = Ustr::from("vcs_kind");
     static ref VCS_REPOSITORY_ROOT: Ustr =
         Ustr::from("vcs_repository_root");
+
+    // Attributes that have their own variant.  Using them with the wrong
+    // kind of index is an error.
+    static ref KNOWN_ATTRIBUTES: Vec<Ustr> = vec![
+        *BODY_SUFFIX, *BODY, *DEFAULT_SWITCHES, *DOT_REPLACEMENT,
+        *EXCLUDED_SOURCE_FILES, *EXEC_DIR, *EXECUTABLE, *EXTERNALLY_BUILT,
+        *GLOBAL_CONFIGURATION_PRAGMAS, *IMPLEMENTATION, *IMPLEMENTATION_SUFFIX,
+        *LANGUAGES, *LIBRARY_DIR, *LIBRARY_INTERFACE, *LIBRARY_KIND,
+        *LIBRARY_NAME, *LIBRARY_OPTIONS, *LIBRARY_STANDALONE,
+        *LIBRARY_VERSION, *LINKER_OPTIONS, *LOCAL_CONFIGURATION_PRAGMAS,
+        *LOCALLY_REMOVED_FILES, *MAIN, *OBJECT_DIR, *PROJECT_FILES,
+        *SHARED_LIBRARY_PREFIX, *SOURCE_DIRS, *SOURCE_FILES,
+        *SOURCE_LIST_FILE, *SPEC, *SPEC_SUFFIX, *SPECIFICATION,
+        *SPECIFICATION_SUFFIX, *SWITCHES, *TARGET, *VCS_KIND,
+        *VCS_REPOSITORY_ROOT,
+    ];
+
+    // Attributes indexed by a language name, which is case-insensitive
+    static ref LANGUAGE_INDEXED: Vec<Ustr> = vec![
+        Ustr::from("dependency_switches"),
+        Ustr::from("driver"),
+        Ustr::from("include_switches"),
+        Ustr::from("leading_required_switches"),
+        Ustr::from("object_file_suffix"),
+        Ustr::from("required_switches"),
+        Ustr::from("runtime"),
+        Ustr::from("runtime_dir"),
+        Ustr::from("separate_suffix"),
+        Ustr::from("trailing_required_switches"),
+    ];
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum SimpleName {
-    Name(Ustr),       // Either variable or attribute name, lower-cased
-    BodySuffix(Ustr), // indexed on lower-cased language
-    Body(Ustr),       // indexed on file basename, casing preserved
+    // Either variable or attribute name, lower-cased
+    Name(#[serde(with = "crate::cache::ustr_serde")] Ustr),
+    // indexed on lower-cased language
+    BodySuffix(#[serde(with = "crate::cache::ustr_serde")] Ustr),
+    // indexed on file basename, casing preserved
+    Body(#[serde(with = "crate::cache::ustr_serde")] Ustr),
     DefaultSwitches(StringOrOthers), // indexed on lower-cased language
     DotReplacement,
     ExcludedSourceFiles,
     ExecDir,
-    Executable(Ustr), // indexed on file basename, casing preserved
-    ExternallyBuilt,  // "true" or "false", lower-cased
+    // indexed on file basename, casing preserved
+    Executable(#[serde(with = "crate::cache::ustr_serde")] Ustr),
+    ExternallyBuilt, // "true" or "false", lower-cased
     GlobalConfigurationPragmas,
     Languages, // lower-cased
     LibraryDir,
@@ -84,13 +127,23 @@ pub enum SimpleName {
     SharedLibraryPrefix,
     SourceDirs,
     SourceFiles,
-    Spec(Ustr),       // indexed on file basename, casing preserved
-    SpecSuffix(Ustr), // indexed on lower-cased language
+    // indexed on file basename, casing preserved
+    Spec(#[serde(with = "crate::cache::ustr_serde")] Ustr),
+    // indexed on lower-cased language
+    SpecSuffix(#[serde(with = "crate::cache::ustr_serde")] Ustr),
     SourceListFile,
     Switches(StringOrOthers), // indexed on lower-cased language
     Target,
     VCSKind,
     VCSRepositoryRoot,
+
+    // Any other attribute, which gprdeps doesn't need to interpret.
+    Attribute {
+        #[serde(with = "crate::cache::ustr_serde")]
+        name: Ustr, // lower-cased
+        index: Option<StringOrOthers>, // lower-cased if case-insensitive
+        case_insensitive: bool,        // whether the index is case-insensitive
+    },
 }
 impl SimpleName {
     /// Builds a variable name
@@ -99,16 +152,21 @@ impl SimpleName {
     }
 
     /// Builds an attribute name
-    /// Properly detects whether an index was needed or not
+    /// Properly detects whether an index was needed or not.  Attributes not
+    /// known to gprdeps are accepted as a generic `Attribute`.
     pub fn new_attr(
         lower: Ustr,
         index: Option<StringOrOthers>,
     ) -> Result<Self, Error> {
         match (lower, index) {
-            (a, Some(StringOrOthers::Str(idx))) if a == *BODY_SUFFIX => {
+            (a, Some(StringOrOthers::Str(idx)))
+                if a == *BODY_SUFFIX || a == *IMPLEMENTATION_SUFFIX =>
+            {
                 Ok(SimpleName::BodySuffix(idx))
             }
-            (a, Some(StringOrOthers::Str(idx))) if a == *BODY => {
+            (a, Some(StringOrOthers::Str(idx)))
+                if a == *BODY || a == *IMPLEMENTATION =>
+            {
                 Ok(SimpleName::Body(idx))
             }
             (a, Some(idx)) if a == *DEFAULT_SWITCHES => {
@@ -117,7 +175,10 @@ impl SimpleName {
             (a, None) if a == *DOT_REPLACEMENT => {
                 Ok(SimpleName::DotReplacement)
             }
-            (a, None) if a == *EXCLUDED_SOURCE_FILES => {
+            (a, None)
+                if a == *EXCLUDED_SOURCE_FILES
+                    || a == *LOCALLY_REMOVED_FILES =>
+            {
                 Ok(SimpleName::ExcludedSourceFiles)
             }
             (a, None) if a == *EXEC_DIR => Ok(SimpleName::ExecDir),
@@ -161,10 +222,14 @@ impl SimpleName {
             (a, None) if a == *SOURCE_LIST_FILE => {
                 Ok(SimpleName::SourceListFile)
             }
-            (a, Some(StringOrOthers::Str(idx))) if a == *SPEC => {
+            (a, Some(StringOrOthers::Str(idx)))
+                if a == *SPEC || a == *SPECIFICATION =>
+            {
                 Ok(SimpleName::Spec(idx))
             }
-            (a, Some(StringOrOthers::Str(idx))) if a == *SPEC_SUFFIX => {
+            (a, Some(StringOrOthers::Str(idx)))
+                if a == *SPEC_SUFFIX || a == *SPECIFICATION_SUFFIX =>
+            {
                 Ok(SimpleName::SpecSuffix(idx))
             }
             (a, Some(idx)) if a == *SWITCHES => Ok(SimpleName::Switches(idx)),
@@ -173,6 +238,13 @@ impl SimpleName {
             (a, None) if a == *VCS_REPOSITORY_ROOT => {
                 Ok(SimpleName::VCSRepositoryRoot)
             }
+            (a, index) if !KNOWN_ATTRIBUTES.contains(&a) => {
+                Ok(SimpleName::Attribute {
+                    name: a,
+                    index,
+                    case_insensitive: SimpleName::is_case_insensitive(&a).0,
+                })
+            }
             (_, None) => Err(Error::InvalidAttribute(lower)),
             (_, Some(StringOrOthers::Str(idx))) => {
                 Err(Error::InvalidAttributeWithIndex(lower, idx))
@@ -192,8 +264,13 @@ impl SimpleName {
             || *lower == *SPEC
             || *lower == *BODY_SUFFIX
             || *lower == *SPEC_SUFFIX
+            || *lower == *IMPLEMENTATION
+            || *lower == *SPECIFICATION
+            || *lower == *IMPLEMENTATION_SUFFIX
+            || *lower == *SPECIFICATION_SUFFIX
             || *lower == *SWITCHES
             || *lower == *DEFAULT_SWITCHES
+            || LANGUAGE_INDEXED.contains(lower)
         {
             (true, false) // case-insensitive index, case-sensitive value
         } else {
@@ -248,6 +325,14 @@ impl std::fmt::Display for SimpleName {
             SimpleName::Target => write!(f, "'target"),
             SimpleName::VCSKind => write!(f, "'vcs_kind"),
             SimpleName::VCSRepositoryRoot => write!(f, "'vcs_repository_root"),
+            SimpleName::Attribute {
+                name, index: None, ..
+            } => write!(f, "'{}", name),
+            SimpleName::Attribute {
+                name,
+                index: Some(idx),
+                ..
+            } => write!(f, "'{}({})", name, idx),
         }
     }
 }
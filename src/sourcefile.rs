@@ -1,30 +1,70 @@
 use crate::{
-    ada_lexer::{AdaLexer, AdaLexerOptions},
-    ada_scanner::AdaScanner,
-    cpp_lexer::CppLexer,
-    cpp_scanner::CppScanner,
-    errors::Error,
-    files::File,
-    graph::NodeIndex,
-    qnames::QName,
+    errors::Error, graph::NodeIndex, qnames::QName, scanners::SCANNERS,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use ustr::Ustr;
 
 /// What is the semantic of a source file within a unit.
-/// In C, units are made up of a single file, so this is always the
-/// implementation.
-#[derive(Debug, Copy, Clone)]
+/// In C, a unit is made up of a header (the spec) and a body with the same
+/// base name, as found from the naming scheme of the project.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum SourceKind {
     Spec,
     Implementation,
     Separate,
 }
 
+/// A C or C++ `#include` directive.  System includes (`<...>`) are not
+/// searched in the directory of the including file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Include {
+    pub path: Ustr,
+    pub system: bool,
+
+    // The preprocessor condition under which the file is included, for
+    // instance when the directive is inside a #ifdef.
+    pub condition: Option<Ustr>,
+}
+
+/// The kind of a dependency.  A `limited with` does not create an
+/// elaboration or compilation dependency, and is allowed in cycles.  A
+/// `private with` is only visible in the private part.  Dependencies in other
+/// languages are always regular.
+/// Kinds are ordered from the weakest to the strongest dependency.
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Serialize,
+)]
+pub enum ImportKind {
+    Limited,
+    Private,
+    Regular,
+}
+
 pub struct ParseResult {
     pub unitname: QName,
     pub kind: SourceKind,
-    pub deps: std::collections::HashSet<QName>,
+    pub deps: HashMap<QName, ImportKind>,
+    pub includes: Vec<Include>, // in the order they appear in the file
+}
+
+impl ParseResult {
+    /// Add a dependency.  When a unit is imported several times, we keep the
+    /// strongest kind.
+    pub fn add_dep(&mut self, name: QName, kind: ImportKind) {
+        let k = self.deps.entry(name).or_insert(kind);
+        *k = (*k).max(kind);
+    }
 }
 
 #[derive(Debug)]
@@ -35,7 +75,8 @@ pub struct SourceFile {
     pub kind: SourceKind,
     pub file_node: NodeIndex, // Node for the source file
     pub unit_node: Option<NodeIndex>, // The node for the unit in the graph
-    pub deps: std::collections::HashSet<QName>,
+    pub deps: HashMap<QName, ImportKind>,
+    pub includes: Vec<Include>,
 
     // Is this file ever marked as a Library_Interface for one project in
     // one scenario ?
@@ -50,37 +91,32 @@ impl SourceFile {
     /// It should return an empty unit name if the file should be ignored (for
     /// instance in Ada there is a `pragma no_body`, or in C there are
     /// preprocessor directives that make the file empty for the compiler).
-    pub fn new(
-        path: &Path,
-        lang: Ustr,
-        file_node: NodeIndex,
-    ) -> Result<Self, Error> {
-        let mut file = File::new(path)?;
-        let info = match lang.as_str() {
-            "ada" => AdaScanner::parse(AdaLexer::new(
-                &mut file,
-                AdaLexerOptions {
-                    kw_aggregate: false,
-                    kw_body: true,
-                },
-            )?)?,
-            "c" | "c++" => CppScanner::parse(CppLexer::new(&mut file)?, path)?,
-            lang => Err(Error::CannotParse {
-                path: path.into(),
-                lang: lang.into(),
-            })?,
-        };
+    pub fn parse(path: &Path, lang: Ustr) -> Result<ParseResult, Error> {
+        SCANNERS.parse(path, lang)
+    }
 
-        Ok(SourceFile {
+    /// Create a source file.  Its unit and dependencies are only known once
+    /// the file has been parsed, see `set_parse_result()`.
+    pub fn new(path: &Path, lang: Ustr, file_node: NodeIndex) -> Self {
+        SourceFile {
             path: path.to_owned(),
             lang,
             file_node,
             unit_node: None,
-            unitname: info.unitname,
-            kind: info.kind,
-            deps: info.deps,
+            unitname: QName::default(),
+            kind: SourceKind::Implementation,
+            deps: Default::default(),
+            includes: Vec::new(),
             is_library_interface: false,
             is_ever_main: false,
-        })
+        }
+    }
+
+    /// Store the result of `parse()`
+    pub fn set_parse_result(&mut self, info: ParseResult) {
+        self.unitname = info.unitname;
+        self.kind = info.kind;
+        self.deps = info.deps;
+        self.includes = info.includes;
     }
 }
@@ -25,8 +25,13 @@ pub enum TokenKind {
     Function,
     Generic,
     GreaterThan,
-    HashInclude(Ustr),
-    Identifier(Ustr), // lower-cased
+    HashElif(Ustr), // condition of #elif
+    HashElse,
+    HashEndif,
+    HashIf(Ustr),            // condition of #if, #ifdef and #ifndef
+    HashInclude(Ustr),       // #include "..."
+    HashIncludeSystem(Ustr), // #include <...>
+    Identifier(Ustr),        // lower-cased
     InvalidChar(char),
     Is,
     LessThan,
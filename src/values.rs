@@ -1,13 +1,55 @@
 use crate::{
-    allscenarios::AllScenarios, errors::Error, gpr::GprFile,
-    packagename::PackageName, perscenario::PerScenario,
-    qualifiedname::QualifiedName, rawexpr::RawExpr, scenarios::Scenario,
+    allscenarios::{AllScenarios, ScenarioRemap},
+    errors::Error,
+    gpr::{Dependencies, GprFile},
+    packagename::PackageName,
+    perscenario::PerScenario,
+    qualifiedname::QualifiedName,
+    rawexpr::RawExpr,
+    scenarios::Scenario,
     simplename::SimpleName,
 };
 use itertools::join;
+use regex::Regex;
 use std::collections::HashSet;
 use ustr::Ustr;
 
+/// Split a string on a separator, ignoring empty elements
+fn split(value: &str, sep: &str) -> Vec<Ustr> {
+    if sep.is_empty() {
+        return vec![Ustr::from(value)];
+    }
+    value
+        .split(sep)
+        .filter(|s| !s.is_empty())
+        .map(Ustr::from)
+        .collect()
+}
+
+/// Compile a regular expression given in a project
+fn build_regex(pattern: Ustr) -> Result<Regex, Error> {
+    Regex::new(pattern.as_str())
+        .map_err(|e| Error::InvalidRegexp(pattern, e.to_string()))
+}
+
+/// Convert a replacement string from gprbuild's syntax ("\1") to the one
+/// used by the regex crate ("${1}")
+fn gpr_replacement(repl: &str) -> String {
+    let mut result = String::new();
+    let mut chars = repl.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(d)) if d.is_ascii_digit() => {
+                result.push_str(&format!("${{{}}}", d));
+                chars.next();
+            }
+            ('$', _) => result.push_str("$$"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ExprValue {
     Str(PerScenario<Ustr>),
@@ -25,6 +67,20 @@ impl ExprValue {
         ExprValue::StrList(PerScenario::new(list))
     }
 
+    /// Convert the scenarios of the value to the bits of another
+    /// AllScenarios
+    pub fn remap_scenarios(&mut self, remap: &ScenarioRemap) {
+        match self {
+            ExprValue::Str(v) => {
+                *v = v.iter().map(|(s, v)| (remap.remap(s), *v)).collect()
+            }
+            ExprValue::StrList(v) => {
+                *v =
+                    v.iter().map(|(s, v)| (remap.remap(s), v.clone())).collect()
+            }
+        }
+    }
+
     /// Evaluate a raw expression into its final value.
     /// The expression is initially seen in the context of one scenario (matching
     /// the case and when clauses), but its final value might be split into
@@ -32,9 +88,9 @@ impl ExprValue {
     pub fn new_with_raw(
         expr: &RawExpr,
         gpr: &GprFile, //  what project was this expression read in ?
-        gpr_deps: &[&GprFile],
+        gpr_deps: &Dependencies,
         scenars: &mut AllScenarios,
-        context: Scenario,
+        context: &Scenario,
         current_pkg: PackageName,
     ) -> Result<Self, Error> {
         match expr {
@@ -48,34 +104,15 @@ impl ExprValue {
                     name: SimpleName::Name(n),
                 },
                 args,
-            )) => match n.as_ref() {
-                "external" => {
-                    let varname = match &args[0] {
-                        RawExpr::Str(v) => v,
-                        _ => panic!(
-                            "Expected static string for variable \
-                                     name in {:?}",
-                            expr
-                        ),
-                    };
-                    let default = match args.get(1) {
-                        None => ExprValue::new_with_str(Ustr::from("")),
-                        Some(expr) => ExprValue::new_with_raw(
-                            expr,
-                            gpr,
-                            gpr_deps,
-                            scenars,
-                            context,
-                            current_pkg,
-                        )?,
-                    };
-                    match &std::env::var(varname.as_str()) {
-                        Ok(v) => Ok(ExprValue::new_with_str(Ustr::from(v))),
-                        Err(_) => Ok(default),
-                    }
-                }
-                _ => Err(Error::UnknownFunction(*n)),
-            },
+            )) => ExprValue::new_with_func(
+                *n,
+                args,
+                gpr,
+                gpr_deps,
+                scenars,
+                context,
+                current_pkg,
+            ),
             RawExpr::FuncCall(_) => {
                 Err(Error::UnknownFunction(Ustr::from(&format!("{:?}", expr))))
             }
@@ -156,6 +193,175 @@ impl ExprValue {
         }
     }
 
+    /// Evaluate a call to one of the built-in functions.
+    /// The first argument is evaluated for all scenarios, so that for instance
+    /// Split on a scenario variable returns a different list for each value
+    /// of the variable.  Separators, prefixes and patterns must be static
+    /// strings.
+    fn new_with_func(
+        name: Ustr,
+        args: &[RawExpr],
+        gpr: &GprFile,
+        gpr_deps: &Dependencies,
+        scenars: &mut AllScenarios,
+        context: &Scenario,
+        current_pkg: PackageName,
+    ) -> Result<Self, Error> {
+        let static_arg = |idx: usize| -> Result<Ustr, Error> {
+            match args.get(idx) {
+                Some(RawExpr::Str(s)) => Ok(*s),
+                Some(_) => Err(Error::NotStaticString),
+                None => Err(Error::WrongFunctionArguments(name)),
+            }
+        };
+        let mut eval_arg = |idx: usize| -> Result<ExprValue, Error> {
+            match args.get(idx) {
+                Some(expr) => ExprValue::new_with_raw(
+                    expr,
+                    gpr,
+                    gpr_deps,
+                    scenars,
+                    context,
+                    current_pkg,
+                ),
+                None => Err(Error::WrongFunctionArguments(name)),
+            }
+        };
+
+        match name.as_str() {
+            "external" => {
+                let varname = static_arg(0)?;
+                let default = match args.get(1) {
+                    None => ExprValue::new_with_str(Ustr::from("")),
+                    Some(_) => eval_arg(1)?,
+                };
+                match gpr_deps.read_external(varname) {
+                    Some(v) => Ok(ExprValue::new_with_str(Ustr::from(&v))),
+                    None => Ok(default),
+                }
+            }
+            "external_as_list" => {
+                let varname = static_arg(0)?;
+                let sep = static_arg(1)?;
+                Ok(ExprValue::new_with_list(
+                    match gpr_deps.read_external(varname) {
+                        Some(v) => split(&v, &sep),
+                        None => vec![],
+                    },
+                ))
+            }
+            "split" => {
+                let sep = static_arg(1)?;
+                match eval_arg(0)? {
+                    ExprValue::Str(v) => {
+                        Ok(ExprValue::StrList(v.map(|s| split(s, &sep))))
+                    }
+                    ExprValue::StrList(_) => {
+                        Err(Error::WrongFunctionArguments(name))
+                    }
+                }
+            }
+            "lower" => Ok(eval_arg(0)?
+                .filter_map(|s| Some(Ustr::from(&s.to_lowercase())))),
+            "upper" => Ok(eval_arg(0)?
+                .filter_map(|s| Some(Ustr::from(&s.to_uppercase())))),
+            "remove_prefix" => {
+                let prefix = static_arg(1)?;
+                Ok(eval_arg(0)?.filter_map(|s| {
+                    Some(Ustr::from(
+                        s.strip_prefix(prefix.as_str()).unwrap_or(s),
+                    ))
+                }))
+            }
+            "remove_suffix" => {
+                let suffix = static_arg(1)?;
+                Ok(eval_arg(0)?.filter_map(|s| {
+                    Some(Ustr::from(
+                        s.strip_suffix(suffix.as_str()).unwrap_or(s),
+                    ))
+                }))
+            }
+            "filter_out" => {
+                let re = build_regex(static_arg(1)?)?;
+                match eval_arg(0)? {
+                    v @ ExprValue::StrList(_) => {
+                        Ok(v.filter_map(|s| (!re.is_match(s)).then_some(*s)))
+                    }
+                    ExprValue::Str(_) => {
+                        Err(Error::WrongFunctionArguments(name))
+                    }
+                }
+            }
+            "match" => {
+                let re = build_regex(static_arg(1)?)?;
+                let replacement = match args.get(2) {
+                    None => None,
+                    Some(_) => Some(gpr_replacement(&static_arg(2)?)),
+                };
+                Ok(eval_arg(0)?.filter_map(|s| {
+                    let caps = re.captures(s)?;
+                    match &replacement {
+                        None => Some(*s),
+                        Some(repl) => {
+                            let mut res = String::new();
+                            caps.expand(repl, &mut res);
+                            Some(Ustr::from(&res))
+                        }
+                    }
+                }))
+            }
+            "default" | "alternative" => {
+                let mut value = eval_arg(0)?;
+                let other = eval_arg(1)?;
+                let is_default = name == "default";
+
+                // "Default" returns the second argument when the first one is
+                // empty, "Alternative" returns it when the first one is not.
+                match (&mut value, &other) {
+                    (ExprValue::Str(v), ExprValue::Str(o)) => {
+                        v.update(o, context, scenars, |v, o| {
+                            *v = match (v.is_empty(), is_default) {
+                                (true, true) | (false, false) => *o,
+                                (false, true) => *v,
+                                (true, false) => Ustr::default(),
+                            };
+                        });
+                    }
+                    (ExprValue::StrList(v), ExprValue::StrList(o)) => {
+                        v.update(o, context, scenars, |v, o| {
+                            match (v.is_empty(), is_default) {
+                                (true, true) | (false, false) => {
+                                    v.clone_from(o)
+                                }
+                                (false, true) | (true, false) => {}
+                            }
+                        });
+                    }
+                    _ => Err(Error::WrongFunctionArguments(name))?,
+                }
+                Ok(value)
+            }
+            _ => Err(Error::UnknownFunction(name)),
+        }
+    }
+
+    /// Transform each string in the value, for all scenarios.  For a list,
+    /// elements for which `transform` returns None are removed.  For a string,
+    /// they are replaced with an empty string.
+    fn filter_map<F>(&self, transform: F) -> Self
+    where
+        F: Fn(&Ustr) -> Option<Ustr>,
+    {
+        match self {
+            ExprValue::Str(v) => {
+                ExprValue::Str(v.map(|s| transform(s).unwrap_or_default()))
+            }
+            ExprValue::StrList(v) => ExprValue::StrList(
+                v.map(|l| l.iter().filter_map(&transform).collect()),
+            ),
+        }
+    }
+
     /// Find all scenarios that result in different values in the project
     pub fn find_used_scenarios(&self, scenars: &mut HashSet<Scenario>) {
         match self {
@@ -190,7 +396,7 @@ mod tests {
     use crate::{
         allscenarios::AllScenarios,
         errors::Error,
-        gpr::GprFile,
+        gpr::{Dependencies, GprFile},
         graph::NodeIndex,
         packagename::PackageName,
         qualifiedname::QualifiedName,
@@ -229,9 +435,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr1,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_str(Ustr::from("value")),
@@ -243,9 +449,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr2,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_str(Ustr::from("valuesuffix")),
@@ -257,9 +463,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr3,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_list(vec![
@@ -277,9 +483,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr4,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             // " valuesuffix, val2",
@@ -296,9 +502,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr4,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_list(vec![
@@ -315,9 +521,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr5,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_list(vec![
@@ -333,7 +539,7 @@ mod tests {
         gpr.declare(
             PackageName::None,
             SimpleName::Name(Ustr::from("var1")),
-            Scenario::default(),
+            &Scenario::default(),
             &mut scenars,
             ExprValue::new_with_str(Ustr::from("val1")),
         )?;
@@ -348,9 +554,9 @@ mod tests {
             ExprValue::new_with_raw(
                 &expr,
                 &gpr,
-                &[],
+                &Dependencies::new(&[]),
                 &mut scenars,
-                Scenario::default(),
+                &Scenario::default(),
                 pkg
             )?,
             ExprValue::new_with_str(Ustr::from("valueval1")),
@@ -359,6 +565,80 @@ mod tests {
         Ok(())
     }
 
+    /// Built-in functions are evaluated for each scenario
+    #[test]
+    fn builtin_functions() -> Result<(), Error> {
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               type T is ("a,b", "c");
+               E1 : T := external ("e1");
+               V1 := Split (E1, ",");
+               V2 := Upper (E1);
+               V3 := Lower (("A", "B"));
+               V4 := Remove_Suffix (("x.adb", "y.ads"), ".adb");
+               V5 := Remove_Prefix ("lib-foo", "lib-");
+               V6 := Default ("", "def") & Default ("val", "def");
+               V7 := Alternative (E1, "alt") & Alternative ("", "alt");
+               V8 := Filter_Out (("a.adb", "b.ads", "c.adb"), "\.ads$");
+               V9 := Match ("lib-1.2", "lib-(.*)", "v\1");
+               V10 := Match (("a.adb", "b.ads"), "\.adb$");
+               V11 := External_As_List ("GPRDEPS_UNDEFINED_VAR", ",");
+               end P;"#,
+        )?;
+        let mut scenarios = crate::allscenarios::AllScenarios::default();
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios)?;
+        let check = |name: &str, expected: &str| {
+            crate::gpr::tests::assert_variable(
+                &gpr,
+                PackageName::None,
+                name,
+                &scenarios,
+                expected,
+            )
+        };
+        check("v1", "e1=a,b a, b\ne1=c   c");
+        check("v2", "e1=a,b A,B\ne1=c   C");
+        check("v3", "* a, b");
+        check("v4", "* x, y.ads");
+        check("v5", "* foo");
+        check("v6", "* defval");
+        check("v7", "e1=a,b alt\ne1=c   alt");
+        check("v8", "* a.adb, c.adb");
+        check("v9", "* v1.2");
+        check("v10", "* a.adb");
+        check("v11", "* ");
+
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               V := Split (("a"), ",");
+               end P;"#,
+        )?;
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios);
+        assert_err!(gpr, Err(Error::WithPath {error, ..})
+            if matches!(*error, Error::WrongFunctionArguments(_)));
+
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               V := External ("GPRDEPS_" & "VAR");
+               end P;"#,
+        )?;
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios);
+        assert_err!(gpr, Err(Error::WithPath {error, ..})
+            if matches!(*error, Error::NotStaticString));
+
+        // Same for the name of a scenario variable
+        let raw = crate::gpr::tests::parse(
+            r#"project P is
+               type T is ("a", "b");
+               E : T := External ("GPRDEPS_" & "VAR");
+               end P;"#,
+        )?;
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios);
+        assert_err!(gpr, Err(Error::WithPath {error, ..})
+            if matches!(*error, Error::NotStaticString));
+        Ok(())
+    }
+
     /// A list expression is built with values that differ between scenarios.
     /// The resulting expression should therefore have different values for
     /// each scenario (four combinations here).
@@ -464,8 +744,8 @@ mod tests {
         Ok(())
     }
 
-    /// Check what happens when we have too many scenario variables and too
-    /// many valid values (overflow of the Mask)
+    /// Check that we support more than 64 valid values for all scenario
+    /// variables (the width of a single word in the Mask)
     #[test]
     fn mask_overflow() -> Result<(), Error> {
         let raw = crate::gpr::tests::parse(
@@ -488,13 +768,39 @@ mod tests {
                E15 : T := external ("e15");
                E16 : T := external ("e16");
                E17 : T := external ("e17");
+               V := "x";
+               case E1 is
+                  when "d" => V := "y";
+                  when others =>
+                     case E17 is
+                        when "d" => V := "y";
+                        when others => null;
+                     end case;
+               end case;
                end P;
             "#,
         )?;
         let mut scenarios = crate::allscenarios::AllScenarios::default();
-        let gpr = crate::gpr::tests::process(&raw, &mut scenarios);
-        assert_err!(gpr, Err(Error::WithPath {error, ..})
-            if matches!(*error, Error::TooManyScenarioVariables));
+        let gpr = crate::gpr::tests::process(&raw, &mut scenarios)?;
+        crate::gpr::tests::assert_variable(
+            &gpr,
+            PackageName::None,
+            "e17",
+            &scenarios,
+            "e17=a a\n\
+             e17=b b\n\
+             e17=c c\n\
+             e17=d d",
+        );
+        crate::gpr::tests::assert_variable(
+            &gpr,
+            PackageName::None,
+            "v",
+            &scenarios,
+            "e1=a|b|c,e17=a|b|c x\n\
+             e1=a|b|c,e17=d     y\n\
+             e1=d               y",
+        );
 
         Ok(())
     }